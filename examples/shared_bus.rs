@@ -0,0 +1,67 @@
+#![no_std]
+#![no_main]
+
+use bmi088::{acc_impl::AccelerometerRange, register_address, shared_bus::SharedSpiBus, Bmi088};
+use defmt::{error, info};
+use defmt_rtt as _;
+use embassy_executor::Spawner;
+use embassy_stm32::{
+    gpio::{Level, Output, Speed},
+    spi::{Config, Spi},
+    time::Hertz,
+};
+use embassy_time::{Delay, Timer};
+use panic_probe as _;
+
+#[embassy_executor::main]
+async fn main(_spawner: Spawner) {
+    let p = embassy_stm32::init(Default::default());
+
+    let mut spi_config = Config::default();
+    spi_config.frequency = Hertz(1_000_000);
+
+    let spi = Spi::new(
+        p.SPI2, p.PB13, p.PC3, p.PC2, p.DMA1_CH4, p.DMA1_CH3, spi_config,
+    );
+    let gyro_cs = Output::new(p.PC14, Level::High, Speed::High);
+    let acc_cs = Output::new(p.PC13, Level::High, Speed::High);
+
+    let bus = SharedSpiBus::new(spi, Delay);
+    let (mut acc, mut gyro) = Bmi088::new_with_shared_spi(&bus, acc_cs, gyro_cs);
+
+    acc.soft_reset().await.unwrap();
+    gyro.soft_reset().await.unwrap();
+
+    Timer::after_millis(1).await;
+
+    acc.dummy_read().await.unwrap();
+
+    info!("acc chip id: {:02X}", acc.chip_id().await.unwrap());
+    info!("gyro chip id: {:02X}", gyro.chip_id().await.unwrap());
+
+    if gyro.check_sensor().await.is_err() {
+        error!("Check gyro failed");
+    }
+
+    acc.set_pwr_save(0x00).await.unwrap();
+    acc.enable_acc().await.unwrap();
+    acc.set_conf(
+        register_address::acc::Conf::ODR::Hz1600.value
+            | register_address::acc::Conf::BWP::OSR4.value,
+    )
+    .await
+    .unwrap();
+    acc.set_range(AccelerometerRange::Scale12g).await.unwrap();
+    gyro.set_bandwidth(0x81).await.unwrap();
+
+    Timer::after_micros(450).await;
+
+    loop {
+        if let Ok((acc_x, acc_y, acc_z)) = acc.xyz().await {
+            info!("acc_x: {} g, acc_y: {} g, acc_z: {} g", acc_x, acc_y, acc_z);
+        }
+        let (x, y, z) = gyro.data().await.unwrap();
+        info!("gyro_x: {} dps, gyro_y: {} dps, gyro_z: {} dps", x, y, z);
+        Timer::after_millis(100).await;
+    }
+}
@@ -0,0 +1,182 @@
+//! Helpers for running the driver against a simulated chip on the host
+//!
+//! Run with `cargo test` from this directory.
+
+use std::{
+    cell::RefCell,
+    future::Future,
+    pin::pin,
+    rc::Rc,
+    task::{Context, Poll, Waker},
+};
+
+use embedded_hal_async::{
+    delay::DelayNs,
+    i2c::{self, ErrorKind, ErrorType, I2c, Operation},
+};
+
+/// Drive a future that never waits on anything outside the test
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let mut cx = Context::from_waker(Waker::noop());
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+    }
+}
+
+/// Delay that returns at once
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Which die a [`FakeBus`] simulates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Die {
+    Accelerometer,
+    Gyroscope,
+}
+
+#[derive(Debug)]
+pub struct Chip {
+    pub die: Die,
+    pub registers: [u8; 128],
+    /// Every register write in order
+    pub writes: Vec<(u8, u8)>,
+    /// Fail this many of the following transfers
+    pub fail: u32,
+    /// Acknowledge but lose this many of the following register writes
+    pub drop_writes: u32,
+    /// Never complete a transfer, like a bus stuck waiting for the hardware
+    pub hang: bool,
+}
+
+impl Chip {
+    fn reset(&mut self) {
+        self.registers = [0; 128];
+        match self.die {
+            Die::Accelerometer => {
+                self.registers[0x00] = 0x1E;
+                self.registers[0x40] = 0xA8;
+                self.registers[0x41] = 0x01;
+                self.registers[0x7C] = 0x03;
+            }
+            Die::Gyroscope => {
+                self.registers[0x00] = 0x0F;
+                self.registers[0x10] = 0x80;
+                self.registers[0x3C] = 0x12;
+            }
+        }
+    }
+
+    fn write(&mut self, register: u8, value: u8) {
+        self.writes.push((register, value));
+        if self.drop_writes > 0 {
+            self.drop_writes -= 1;
+            return;
+        }
+        match (self.die, register) {
+            (Die::Accelerometer, 0x7E) | (Die::Gyroscope, 0x14) if value == 0xB6 => self.reset(),
+            // Bit 7 of the bandwidth register always reads as 1
+            (Die::Gyroscope, 0x10) => self.registers[0x10] = value | 0x80,
+            _ => self.registers[usize::from(register)] = value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BusError;
+
+impl i2c::Error for BusError {
+    fn kind(&self) -> ErrorKind {
+        ErrorKind::Other
+    }
+}
+
+/// I2C bus with a register map behind it, clones share the chip
+#[derive(Debug, Clone)]
+pub struct FakeBus(Rc<RefCell<Chip>>);
+
+impl FakeBus {
+    /// A die with its power-on register values
+    pub fn new(die: Die) -> Self {
+        let mut chip = Chip {
+            die,
+            registers: [0; 128],
+            writes: Vec::new(),
+            fail: 0,
+            drop_writes: 0,
+            hang: false,
+        };
+        chip.reset();
+        FakeBus(Rc::new(RefCell::new(chip)))
+    }
+
+    pub fn chip(&self) -> std::cell::RefMut<'_, Chip> {
+        self.0.borrow_mut()
+    }
+
+    pub fn register(&self, register: u8) -> u8 {
+        self.0.borrow().registers[usize::from(register)]
+    }
+
+    pub fn set_register(&self, register: u8, value: u8) {
+        self.0.borrow_mut().registers[usize::from(register)] = value;
+    }
+
+    /// Values of consecutive registers from `start`, e.g. a rate sample
+    pub fn set_registers(&self, start: u8, values: &[u8]) {
+        let start = usize::from(start);
+        self.0.borrow_mut().registers[start..start + values.len()].copy_from_slice(values);
+    }
+
+    /// Power-on reset behind the driver's back
+    pub fn power_cycle(&self) {
+        self.0.borrow_mut().reset();
+    }
+}
+
+impl ErrorType for FakeBus {
+    type Error = BusError;
+}
+
+impl I2c for FakeBus {
+    async fn transaction(
+        &mut self,
+        _address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        if self.0.borrow().hang {
+            return std::future::pending().await;
+        }
+        let mut chip = self.0.borrow_mut();
+        if chip.fail > 0 {
+            chip.fail -= 1;
+            return Err(BusError);
+        }
+        let mut pointer = 0usize;
+        for operation in operations {
+            match operation {
+                // The register map ends at 0x7F, there is no read flag
+                Operation::Write([register, ..]) if *register >= 0x80 => return Err(BusError),
+                Operation::Write([register, data @ ..]) => {
+                    pointer = usize::from(*register);
+                    for (i, &value) in data.iter().enumerate() {
+                        chip.write((pointer + i) as u8, value);
+                    }
+                }
+                Operation::Write([]) => {}
+                Operation::Read(buffer) => {
+                    for byte in buffer.iter_mut() {
+                        *byte = chip.registers[pointer % 128];
+                        pointer += 1;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,56 @@
+use bmi088::allan::AllanCurve;
+
+const RATE_HZ: f32 = 100.0;
+const SIGMA: [f32; 3] = [0.1, 0.5, 2.0];
+
+/// Gaussian white noise with a constant offset, σ per axis from `SIGMA`
+fn white_noise(len: usize) -> Vec<[f32; 3]> {
+    let mut seed = 12345u64;
+    let mut uniform = move || {
+        seed = seed
+            .wrapping_mul(6_364_136_223_846_793_005)
+            .wrapping_add(1_442_695_040_888_963_407);
+        ((seed >> 11) as f64 + 0.5) / (1u64 << 53) as f64
+    };
+    let mut gaussian = move || {
+        let (u, v) = (uniform(), uniform());
+        ((-2.0 * u.ln()).sqrt() * (2.0 * std::f64::consts::PI * v).cos()) as f32
+    };
+    (0..len)
+        .map(|_| [0, 1, 2].map(|i| 1.5 + SIGMA[i] * gaussian()))
+        .collect()
+}
+
+#[test]
+fn white_noise_falls_with_half_slope() {
+    let samples = white_noise(100_000);
+    let curve = AllanCurve::<64>::compute(&samples, RATE_HZ, 10);
+    let points = curve.points();
+    assert!((points[0].tau_s - 1.0 / RATE_HZ).abs() < 1e-6);
+
+    // Only clusters with enough independent averages
+    let good: Vec<_> = points
+        .iter()
+        .filter(|p| p.tau_s * RATE_HZ * 100.0 <= samples.len() as f32)
+        .collect();
+    assert!(good.len() > 20);
+    for (axis, sigma) in SIGMA.into_iter().enumerate() {
+        // σ(τ) = σ / √(f τ)
+        for p in &good {
+            let expected = sigma / (RATE_HZ * p.tau_s).sqrt();
+            let error = p.deviation[axis] / expected - 1.0;
+            assert!(error.abs() < 0.1, "axis {axis} at {} s: {error}", p.tau_s);
+        }
+        let (first, last) = (good[0], good[good.len() - 1]);
+        let slope =
+            (last.deviation[axis] / first.deviation[axis]).ln() / (last.tau_s / first.tau_s).ln();
+        assert!((slope + 0.5).abs() < 0.02, "axis {axis}: {slope}");
+    }
+
+    let noise = curve.noise();
+    for (axis, sigma) in SIGMA.into_iter().enumerate() {
+        let expected = sigma / RATE_HZ.sqrt();
+        let error = noise.random_walk[axis] / expected - 1.0;
+        assert!(error.abs() < 0.05, "axis {axis}: {error}");
+    }
+}
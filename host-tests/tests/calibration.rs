@@ -0,0 +1,36 @@
+use bmi088::{
+    calibration::{BiasCalibration, TempModel},
+    gyro_impl::GyroscopeRange,
+    Bmi088,
+};
+use bmi088_host_tests::{block_on, Die, FakeBus, NoDelay};
+
+#[test]
+fn gyro_bias_is_residual_after_temperature_model() {
+    let bus = FakeBus::new(Die::Gyroscope);
+    let mut gyro = Bmi088::new_gyro_with_i2c(bus.clone(), 0x68);
+    block_on(gyro.set_range(GyroscopeRange::Scale125)).unwrap();
+
+    // 0.5 °/s + 0.1 °/s/K offset around 25 °C, read at 30 °C
+    let mut model = TempModel::identity(25.0);
+    model.bias = [[0.5, 0.1, 0.0, 0.0]; 3];
+    gyro.set_temp_model(Some(model));
+    gyro.set_temperature(30.0);
+
+    // 300 LSB at 3.8 m°/s/LSB are 1.14 °/s on every axis
+    let lsb = 300i16.to_le_bytes();
+    bus.set_registers(0x02, &[lsb[0], lsb[1], lsb[0], lsb[1], lsb[0], lsb[1]]);
+
+    let settings = BiasCalibration {
+        samples: 20,
+        ..Default::default()
+    };
+    let bias = block_on(gyro.calibrate_bias(&settings, &mut NoDelay)).unwrap();
+    for b in bias.bias {
+        assert!((b - 0.14).abs() < 1e-4, "{b}");
+    }
+    let sample = block_on(gyro.sample()).unwrap();
+    for x in sample.xyz {
+        assert!(x.abs() < 1e-5, "{x}");
+    }
+}
@@ -0,0 +1,90 @@
+use bmi088::{
+    capture::{CaptureConfig, CaptureSample, CaptureState, PreTrigger},
+    sample::{AccelSample, GyroSample},
+};
+
+const PRE: usize = 3;
+const POST: usize = 4;
+
+fn buffer(threshold_g: Option<f32>) -> PreTrigger<8> {
+    PreTrigger::new(CaptureConfig {
+        pre: PRE,
+        post: POST,
+        threshold_g,
+    })
+    .unwrap()
+}
+
+/// Sample tagged with its sequence number in the sensor time
+fn sample(n: u32, g: f32) -> (AccelSample, GyroSample) {
+    let accel = AccelSample {
+        xyz: [0.0, 0.0, g],
+        sensor_time: n,
+    };
+    (accel, GyroSample::default())
+}
+
+fn times<'a>(samples: impl Iterator<Item = &'a CaptureSample>) -> Vec<u32> {
+    samples.map(|s| s.accel.sensor_time).collect()
+}
+
+#[test]
+fn capture_is_ordered_at_every_ring_offset() {
+    for before in 0..20u32 {
+        let mut buffer = buffer(None);
+        for n in 0..before {
+            let (accel, gyro) = sample(n, 1.0);
+            assert!(!buffer.push(&accel, &gyro));
+        }
+        buffer.trigger();
+        assert!(buffer.capture().is_none());
+        for n in before..before + POST as u32 {
+            let (accel, gyro) = sample(n, 1.0);
+            assert_eq!(buffer.push(&accel, &gyro), n == before + POST as u32 - 1);
+        }
+
+        let capture = buffer.capture().unwrap();
+        let pre = (before as usize).min(PRE) as u32;
+        let expected: Vec<u32> = (before - pre..before + POST as u32).collect();
+        assert_eq!(times(capture.iter()), expected, "{before} before");
+        assert_eq!(capture.len(), expected.len());
+        assert_eq!(capture.trigger_index(), pre as usize);
+        assert_eq!(
+            capture
+                .get(capture.trigger_index())
+                .unwrap()
+                .accel
+                .sensor_time,
+            before
+        );
+        assert_eq!(times(capture.pre_trigger()), expected[..pre as usize]);
+        assert_eq!(times(capture.post_trigger()), expected[pre as usize..]);
+        assert!(capture.get(capture.len()).is_none());
+    }
+}
+
+#[test]
+fn threshold_trigger_starts_with_the_shock_and_freezes() {
+    let mut buffer = buffer(Some(2.0));
+    for n in 0..11 {
+        let (accel, gyro) = sample(n, if n == 9 { 3.0 } else { 1.0 });
+        buffer.push(&accel, &gyro);
+    }
+    assert_eq!(buffer.state(), CaptureState::Triggered { remaining: 2 });
+    for n in 11..20 {
+        let (accel, gyro) = sample(n, 1.0);
+        assert_eq!(buffer.push(&accel, &gyro), n >= 12);
+    }
+    assert_eq!(buffer.state(), CaptureState::Frozen);
+
+    let capture = buffer.capture().unwrap();
+    assert_eq!(times(capture.iter()), [6, 7, 8, 9, 10, 11, 12]);
+    assert_eq!(
+        capture.get(capture.trigger_index()).unwrap().accel.xyz[2],
+        3.0
+    );
+
+    buffer.rearm();
+    assert_eq!(buffer.state(), CaptureState::Armed);
+    assert!(buffer.capture().is_none());
+}
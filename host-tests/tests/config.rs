@@ -0,0 +1,203 @@
+use bmi088::{
+    acc_impl::AccelerometerRange,
+    config::{AccelConfig, DeviceConfig, DeviceError, GyroConfig, ShadowCheck},
+    gyro_impl::{GyroBandwidth, GyroscopeRange},
+    Bmi088, Error, Sensor,
+};
+use bmi088_host_tests::{block_on, Die, FakeBus, NoDelay};
+
+const GYRO: GyroConfig = GyroConfig {
+    range: GyroscopeRange::Scale500 as u8,
+    bandwidth: GyroBandwidth::Odr400Bw47 as u8 | 0x80,
+    lpm1: 0x00,
+};
+
+const ACC: AccelConfig = AccelConfig {
+    conf: 0xAC,
+    range: AccelerometerRange::Scale24g as u8,
+    pwr_conf: 0x00,
+    pwr_ctrl: 0x04,
+};
+
+#[test]
+fn snapshot_round_trips_through_bytes() {
+    let config = DeviceConfig {
+        acc: ACC,
+        gyro: GYRO,
+    };
+    let bytes = config.to_bytes();
+    assert_eq!(bytes, [0xAC, 0x03, 0x00, 0x04, 0x02, 0x83, 0x00]);
+    assert_eq!(DeviceConfig::from_bytes(&bytes), Some(config));
+
+    let mut corrupt = bytes;
+    corrupt[1] = 0x07;
+    assert_eq!(DeviceConfig::from_bytes(&corrupt), None);
+}
+
+#[test]
+fn snapshot_is_restored_onto_a_reset_chip() {
+    let acc_bus = FakeBus::new(Die::Accelerometer);
+    let gyro_bus = FakeBus::new(Die::Gyroscope);
+    let mut acc = Bmi088::new_acc_with_i2c(acc_bus.clone(), 0x18);
+    let mut gyro = Bmi088::new_gyro_with_i2c(gyro_bus.clone(), 0x68);
+
+    let fresh = block_on(DeviceConfig::read(&mut acc, &mut gyro)).unwrap();
+    assert_eq!(fresh, DeviceConfig::default());
+    let config = DeviceConfig {
+        acc: ACC,
+        gyro: GYRO,
+    };
+    block_on(config.apply(&mut acc, &mut gyro, &mut NoDelay)).unwrap();
+    let live = block_on(DeviceConfig::read(&mut acc, &mut gyro)).unwrap();
+    assert_eq!(live, config);
+    assert_ne!(live, fresh);
+
+    acc_bus.power_cycle();
+    gyro_bus.power_cycle();
+    assert_eq!(
+        block_on(DeviceConfig::read(&mut acc, &mut gyro)).unwrap(),
+        fresh
+    );
+    let stored = DeviceConfig::from_bytes(&live.to_bytes()).unwrap();
+    block_on(stored.apply(&mut acc, &mut gyro, &mut NoDelay)).unwrap();
+    assert_eq!(acc_bus.register(0x41), ACC.range);
+    assert_eq!(acc_bus.register(0x7D), ACC.pwr_ctrl);
+    assert_eq!(gyro_bus.register(0x0F), GYRO.range);
+    assert_eq!(
+        block_on(DeviceConfig::read(&mut acc, &mut gyro)).unwrap(),
+        config
+    );
+}
+
+#[test]
+fn invalid_snapshot_is_rejected_before_any_write() {
+    let acc_bus = FakeBus::new(Die::Accelerometer);
+    let gyro_bus = FakeBus::new(Die::Gyroscope);
+    let mut acc = Bmi088::new_acc_with_i2c(acc_bus.clone(), 0x18);
+    let mut gyro = Bmi088::new_gyro_with_i2c(gyro_bus.clone(), 0x68);
+
+    let mut acc_config = ACC;
+    acc_config.range = 0x07;
+    let err = block_on(acc.apply_config(&acc_config, &mut NoDelay)).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::InvalidConfig {
+            sensor: Sensor::Accelerometer,
+            reg: 0x41,
+            value: 0x07
+        }
+    ));
+
+    // A bad gyroscope value leaves the accelerometer untouched as well
+    let config = DeviceConfig {
+        acc: ACC,
+        gyro: GyroConfig {
+            range: 0x05,
+            ..GYRO
+        },
+    };
+    let err = block_on(config.apply(&mut acc, &mut gyro, &mut NoDelay)).unwrap_err();
+    assert!(matches!(
+        err,
+        DeviceError::Gyroscope(Error::InvalidConfig {
+            sensor: Sensor::Gyroscope,
+            reg: 0x0F,
+            value: 0x05
+        })
+    ));
+    assert!(acc_bus.chip().writes.is_empty());
+    assert!(gyro_bus.chip().writes.is_empty());
+}
+
+#[test]
+fn gyro_reattach_keeps_live_configuration() {
+    let bus = FakeBus::new(Die::Gyroscope);
+    let mut gyro = Bmi088::new_gyro_with_i2c(bus.clone(), 0x68);
+    block_on(gyro.apply_config(&GYRO)).unwrap();
+
+    // Only the MCU restarts, the chip keeps its registers
+    let mut gyro = Bmi088::new_gyro_with_i2c(bus.clone(), 0x68);
+    gyro.set_auto_restore(true);
+    assert_eq!(block_on(gyro.sync_shadow()).unwrap(), GYRO);
+    assert_eq!(
+        block_on(gyro.check_shadow()).unwrap(),
+        ShadowCheck::Consistent
+    );
+    assert_eq!(bus.register(0x0F), GYRO.range);
+
+    // A real reset is still detected and undone
+    bus.power_cycle();
+    assert_eq!(
+        block_on(gyro.check_shadow()).unwrap(),
+        ShadowCheck::Restored
+    );
+    assert_eq!(block_on(gyro.read_config()).unwrap(), GYRO);
+}
+
+#[test]
+fn accel_reattach_keeps_live_configuration() {
+    let config = ACC;
+    let bus = FakeBus::new(Die::Accelerometer);
+    let mut acc = Bmi088::new_acc_with_i2c(bus.clone(), 0x18);
+    block_on(acc.apply_config(&config, &mut NoDelay)).unwrap();
+
+    let mut acc = Bmi088::new_acc_with_i2c(bus.clone(), 0x18);
+    acc.set_auto_restore(true);
+    assert_eq!(block_on(acc.sync_shadow()).unwrap(), config);
+    let check = block_on(acc.check_shadow(&mut NoDelay)).unwrap();
+    assert_eq!(check, ShadowCheck::Consistent);
+    assert_eq!(bus.register(0x41), config.range);
+}
+
+#[test]
+fn lost_write_fails_verification() {
+    let bus = FakeBus::new(Die::Accelerometer);
+    let mut acc = Bmi088::new_acc_with_i2c(bus.clone(), 0x18);
+    acc.set_write_verify(true);
+    block_on(acc.apply_config(&ACC, &mut NoDelay)).unwrap();
+
+    bus.chip().drop_writes = 1;
+    let err = block_on(acc.set_range(AccelerometerRange::Scale3g)).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::VerifyFailed {
+            sensor: Sensor::Accelerometer,
+            reg: 0x41,
+            wrote: 0x00,
+            read: 0x03
+        }
+    ));
+
+    // The power registers are checked after the mode change settled
+    bus.power_cycle();
+    bus.chip().drop_writes = 1;
+    let err = block_on(acc.apply_config(&ACC, &mut NoDelay)).unwrap_err();
+    assert!(matches!(
+        err,
+        Error::VerifyFailed {
+            reg: 0x7D,
+            wrote: 0x04,
+            read: 0x00,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn accel_reset_is_detected_and_restored() {
+    let bus = FakeBus::new(Die::Accelerometer);
+    let mut acc = Bmi088::new_acc_with_i2c(bus.clone(), 0x18);
+    block_on(acc.apply_config(&ACC, &mut NoDelay)).unwrap();
+
+    bus.power_cycle();
+    let check = block_on(acc.check_shadow(&mut NoDelay)).unwrap();
+    assert_eq!(check, ShadowCheck::ResetDetected);
+    assert_eq!(block_on(acc.read_config()).unwrap(), AccelConfig::default());
+
+    acc.set_auto_restore(true);
+    let check = block_on(acc.check_shadow(&mut NoDelay)).unwrap();
+    assert_eq!(check, ShadowCheck::Restored);
+    assert_eq!(block_on(acc.read_config()).unwrap(), ACC);
+    let check = block_on(acc.check_shadow(&mut NoDelay)).unwrap();
+    assert_eq!(check, ShadowCheck::Consistent);
+}
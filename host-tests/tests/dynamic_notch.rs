@@ -0,0 +1,79 @@
+use std::f32::consts::PI;
+
+use bmi088::filter::{DynamicNotch, DynamicNotchConfig};
+
+const RATE_HZ: f32 = 2000.0;
+
+fn notch() -> DynamicNotch<64, 2> {
+    let mut notch = DynamicNotch::new(DynamicNotchConfig::default()).unwrap();
+    notch.set_sample_rate(RATE_HZ);
+    notch
+}
+
+fn rms(x: &[f32]) -> f32 {
+    (x.iter().map(|x| x * x).sum::<f32>() / x.len() as f32).sqrt()
+}
+
+#[test]
+fn follows_swept_tone() {
+    let mut notch = notch();
+    // 150 Hz to 450 Hz over 2 s on X, a little broadband noise everywhere
+    let seconds = 2.0;
+    let n = (seconds * RATE_HZ) as usize;
+    let mut phase = 0.0f32;
+    let mut seed = 7u32;
+    let mut noise = || {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        ((seed >> 8) as f32 / (1 << 24) as f32 - 0.5) * 0.2
+    };
+    let (mut input, mut output) = (Vec::new(), Vec::new());
+    for i in 0..n {
+        let hz = 150.0 + 300.0 * i as f32 / n as f32;
+        phase += 2.0 * PI * hz / RATE_HZ;
+        let x = 10.0 * phase.sin();
+        let y = notch.process([x + noise(), noise(), noise()]);
+        if i > n / 4 {
+            input.push(x);
+            output.push(y[0]);
+            // One notch sits on the tone, within a bin
+            let tracked = notch.peaks()[0].iter().flatten();
+            let error = tracked.map(|f| (f - hz).abs()).fold(f32::MAX, f32::min);
+            assert!(error < RATE_HZ / 64.0, "{hz} Hz: {:?}", notch.peaks()[0]);
+        }
+    }
+    assert!(rms(&output) < 0.3 * rms(&input), "{}", rms(&output));
+}
+
+#[test]
+fn notch_stays_with_its_tone_and_is_released() {
+    let mut notch = notch();
+    let mut t = 0.0f32;
+    let mut run = |notch: &mut DynamicNotch<64, 2>, tones: &[f32], seconds: f32| {
+        for _ in 0..(seconds * RATE_HZ) as usize {
+            t += 1.0 / RATE_HZ;
+            let x: f32 = tones
+                .iter()
+                .map(|hz| 10.0 * (2.0 * PI * hz * t).sin())
+                .sum();
+            notch.process([x, 0.0, 0.0]);
+        }
+    };
+
+    run(&mut notch, &[400.0, 200.0], 0.5);
+    let peaks = notch.peaks()[0];
+    let slot = |peaks: [Option<f32>; 2], hz: f32| {
+        peaks
+            .iter()
+            .position(|p| p.is_some_and(|p| (p - hz).abs() < 10.0))
+    };
+    let high = slot(peaks, 400.0).unwrap();
+    let low = slot(peaks, 200.0).unwrap();
+    assert_ne!(high, low);
+
+    // The lower tone stops: the 400 Hz notch keeps its slot and frequency
+    // while the other one is released
+    run(&mut notch, &[400.0], 0.5);
+    let peaks = notch.peaks()[0];
+    assert_eq!(slot(peaks, 400.0), Some(high));
+    assert_eq!(peaks[low], None);
+}
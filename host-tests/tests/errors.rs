@@ -0,0 +1,22 @@
+use bmi088::{register_address::GyroRegisters, Access, Bmi088, Error, Sensor};
+use bmi088_host_tests::{block_on, Die, FakeBus};
+
+#[test]
+fn i2c_burst_error_reports_plain_register() {
+    let bus = FakeBus::new(Die::Gyroscope);
+    let mut gyro = Bmi088::new_gyro_with_i2c(bus.clone(), 0x68);
+    bus.chip().fail = 1;
+    let error = block_on(gyro.sample()).unwrap_err();
+    let context = error.context().unwrap();
+    assert_eq!(context.register, GyroRegisters::RATE_X_LSB as u8);
+    assert_eq!(context.access, Access::Read);
+    assert!(error.is_bus_fault());
+}
+
+#[test]
+fn motion_during_calibration_is_not_a_sensor_fault() {
+    let error: Error<()> = Error::NotStationary(Sensor::Accelerometer);
+    assert_eq!(error.sensor(), Sensor::Accelerometer);
+    assert!(error.is_motion_fault());
+    assert!(!error.is_sensor_fault() && !error.is_bus_fault());
+}
@@ -0,0 +1,146 @@
+use bmi088::{
+    events::{Event, EventConfig, EventDetector, EventKind},
+    sample::{AccelSample, GyroSample},
+};
+
+/// 1600 Hz, 16 sensor time ticks of 39.0625 µs
+const TICKS: u32 = 16;
+const PERIOD_US: u64 = 625;
+const REST: [f32; 3] = [0.0, 0.0, 1.0];
+
+/// Feeds a synthetic trace and collects the events
+struct Trace {
+    detector: EventDetector,
+    sample: u64,
+    events: Vec<Event>,
+}
+
+impl Trace {
+    fn new() -> Self {
+        Trace {
+            detector: EventDetector::new(EventConfig::default()),
+            sample: 0,
+            events: Vec::new(),
+        }
+    }
+
+    fn now_us(&self) -> u64 {
+        self.sample * PERIOD_US
+    }
+
+    /// Hold `acc` in g and `rate` in °/s for `ms` milliseconds
+    fn hold(&mut self, acc: [f32; 3], rate: [f32; 3], ms: u64) -> &mut Self {
+        for _ in 0..ms * 1000 / PERIOD_US {
+            let accel = AccelSample {
+                xyz: acc,
+                sensor_time: self.sample as u32 * TICKS,
+            };
+            let gyro = GyroSample { xyz: rate };
+            let events = self.detector.update(&accel, Some(&gyro));
+            self.events.extend(events.into_iter().flatten());
+            self.sample += 1;
+        }
+        self
+    }
+
+    fn rest(&mut self, ms: u64) -> &mut Self {
+        self.hold(REST, [0.0; 3], ms)
+    }
+
+    fn kinds(&self) -> Vec<EventKind> {
+        self.events.iter().map(|e| e.kind).collect()
+    }
+}
+
+#[test]
+fn stillness_and_motion() {
+    let mut trace = Trace::new();
+    trace.rest(1500);
+    assert_eq!(trace.kinds(), [EventKind::Still]);
+    assert!(trace.detector.is_still());
+    assert!(trace.events[0].time_us <= PERIOD_US);
+
+    // Turning slowly moves nothing but the gyroscope
+    let turn = trace.now_us();
+    trace.hold(REST, [0.0, 0.0, 10.0], 100);
+    assert_eq!(trace.kinds(), [EventKind::Still, EventKind::Moving]);
+    assert_eq!(trace.events[1].time_us, turn);
+    assert!(!trace.detector.is_still());
+}
+
+#[test]
+fn single_tap_is_reported_after_the_double_tap_window() {
+    let mut trace = Trace::new();
+    trace.rest(500);
+    let tap = trace.now_us();
+    trace.hold([2.0, 0.0, 1.0], [0.0; 3], 5).rest(300);
+    assert!(trace.events.is_empty());
+    trace.rest(200);
+    assert_eq!(
+        trace.events,
+        [Event {
+            kind: EventKind::SingleTap,
+            time_us: tap
+        }]
+    );
+}
+
+#[test]
+fn two_taps_make_a_double_tap() {
+    let mut trace = Trace::new();
+    trace.rest(500);
+    let first = trace.now_us();
+    trace
+        .hold([0.0, 2.0, 1.0], [0.0; 3], 5)
+        .rest(200)
+        .hold([0.0, 2.0, 1.0], [0.0; 3], 5)
+        .rest(800);
+    assert_eq!(
+        trace.events,
+        [Event {
+            kind: EventKind::DoubleTap,
+            time_us: first
+        }]
+    );
+}
+
+#[test]
+fn long_shock_is_not_a_tap() {
+    let mut trace = Trace::new();
+    trace
+        .rest(500)
+        .hold([2.0, 0.0, 1.0], [0.0; 3], 100)
+        .rest(800);
+    assert!(trace.events.is_empty(), "{:?}", trace.events);
+}
+
+#[test]
+fn drop_is_a_free_fall_then_an_impact() {
+    let mut trace = Trace::new();
+    trace.rest(500);
+    let fall = trace.now_us();
+    trace.hold([0.0, 0.0, 0.05], [0.0; 3], 200);
+    assert_eq!(
+        trace.events,
+        [Event {
+            kind: EventKind::FreeFall,
+            time_us: fall
+        }]
+    );
+
+    // Landing: a short ramp up to 8 g and back
+    for g in [3.0, 6.0, 8.0, 5.0, 2.0] {
+        trace.hold([0.0, 0.0, g], [0.0; 3], 1);
+    }
+    let peak = fall + 200_000 + 2 * PERIOD_US;
+    trace.rest(800);
+    assert_eq!(
+        trace.events[1],
+        Event {
+            kind: EventKind::Impact { peak_g: 8.0 },
+            time_us: peak
+        }
+    );
+    // The shock is not reported as a tap as well
+    assert_eq!(trace.events.len(), 2, "{:?}", trace.events);
+}
@@ -0,0 +1,35 @@
+use bmi088::{acc_impl::AccelerometerRange, Bmi088};
+use bmi088_host_tests::{block_on, Die, FakeBus};
+
+#[test]
+fn gyro_burst_read_addresses_the_rate_registers() {
+    let bus = FakeBus::new(Die::Gyroscope);
+    let mut gyro = Bmi088::new_gyro_with_i2c(bus.clone(), 0x68);
+    // RATE_X_LSB .. RATE_Z_MSB
+    bus.set_registers(0x02, &[0x34, 0x12, 0xCC, 0xED, 0x00, 0x80]);
+
+    let rate = block_on(gyro.burst_read_xyz_rate()).unwrap();
+    assert_eq!(rate, (0x1234, -0x1234, i16::MIN));
+}
+
+#[test]
+fn accel_burst_reads_without_a_dummy_byte() {
+    let bus = FakeBus::new(Die::Accelerometer);
+    let mut acc = Bmi088::new_acc_with_i2c(bus.clone(), 0x18);
+    block_on(acc.set_range(AccelerometerRange::Scale3g)).unwrap();
+    // STATUS with DRDY, X_LSB .. Z_MSB, SENSORTIME_0 .. SENSORTIME_2
+    bus.set_register(0x03, 0x80);
+    bus.set_registers(
+        0x12,
+        &[0x00, 0x20, 0x00, 0xE0, 0x00, 0x40, 0x56, 0x34, 0x12],
+    );
+
+    let sample = block_on(acc.sample()).unwrap();
+    assert_eq!(sample.xyz, [0.75, -0.75, 1.5]);
+    assert_eq!(sample.sensor_time, 0x12_3456);
+    assert_eq!(block_on(acc.sensor_time_us()).unwrap(), 0x12_3456 * 39);
+
+    // TEMP_MSB, TEMP_LSB: 16 °C above 23 °C
+    bus.set_registers(0x22, &[0x10, 0x00]);
+    assert_eq!(block_on(acc.temperature()).unwrap(), 39.0);
+}
@@ -0,0 +1,129 @@
+use std::f32::consts::PI;
+
+use bmi088::{
+    fusion::{Ins, STANDARD_GRAVITY},
+    sample::{AccelSample, GyroSample},
+};
+
+/// 400 Hz, 64 sensor time ticks of 39.0625 µs
+const TICKS: u32 = 64;
+const DT: f32 = 0.0025;
+
+/// Forward acceleration peak of a step in m/s²
+const STEP_ACCEL: f32 = 8.0;
+/// Foot pitch peak of a step in rad
+const STEP_PITCH: f32 = 0.4;
+const SWING_S: f32 = 0.6;
+
+/// Motion of a foot walking along world X, it keeps rotating throughout
+/// the swing as a real foot does
+#[derive(Clone, Copy)]
+enum Phase {
+    Stance,
+    Swing,
+}
+
+/// Board-frame specific force in g and rate in °/s at `t` into the phase
+fn imu(phase: Phase, t: f32) -> ([f32; 3], [f32; 3]) {
+    let (accel, pitch, rate) = match phase {
+        Phase::Stance => (0.0, 0.0, 0.0),
+        Phase::Swing => {
+            let w = 2.0 * PI / SWING_S;
+            (
+                STEP_ACCEL * (w * t).sin(),
+                STEP_PITCH * (w * t).sin(),
+                STEP_PITCH * w * (w * t).cos(),
+            )
+        }
+    };
+    let (s, c) = pitch.sin_cos();
+    let world = [accel, 0.0, STANDARD_GRAVITY];
+    let board = [
+        c * world[0] - s * world[2],
+        0.0,
+        s * world[0] + c * world[2],
+    ];
+    (
+        board.map(|f| f / STANDARD_GRAVITY),
+        [0.0, rate.to_degrees(), 0.0],
+    )
+}
+
+/// Distance covered by one swing
+fn step_length() -> f32 {
+    STEP_ACCEL * SWING_S * SWING_S / (2.0 * PI)
+}
+
+#[test]
+fn zupt_holds_stance_velocity_and_bounds_drift() {
+    let mut ins = Ins::<3>::default();
+    let mut ticks = 0u32;
+    // Deterministic noise, about ±0.002 g and ±0.05 °/s, plus a gyro bias
+    let mut seed = 1u32;
+    let mut noise = move |scale: f32| {
+        seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+        ((seed >> 8) as f32 / (1 << 24) as f32 - 0.5) * 2.0 * scale
+    };
+    let mut feed =
+        |ins: &mut Ins<3>, phase: Phase, seconds: f32, check: &mut dyn FnMut(&Ins<3>, usize)| {
+            let n = (seconds / DT).round() as usize;
+            for i in 0..n {
+                let (acc, gyro) = imu(phase, i as f32 * DT);
+                let accel = AccelSample {
+                    xyz: acc.map(|a| a + noise(0.002)),
+                    sensor_time: ticks,
+                };
+                let gyro = GyroSample {
+                    xyz: gyro.map(|w| w + 0.05 + noise(0.05)),
+                };
+                ticks += TICKS;
+                ins.update(&accel, &gyro);
+                check(ins, i);
+            }
+        };
+
+    // Settled stance phases, skipping the detector window and the filter's
+    // first corrections
+    let mut stance = |ins: &Ins<3>, i: usize| {
+        if i >= 20 {
+            assert!(ins.is_stationary(), "sample {i}");
+            assert!(ins.speed() < 0.02, "{:?}", ins.velocity());
+            assert!(ins.velocity()[2].abs() < 0.02, "{:?}", ins.velocity());
+        }
+    };
+    let mut swing = |ins: &Ins<3>, i: usize| {
+        // The detector window reaches back into the stance at first
+        assert!(i < 5 || !ins.is_stationary(), "sample {i}");
+        if i == 120 {
+            // Mid-swing the foot moves at its peak speed
+            let peak = STEP_ACCEL * SWING_S / PI;
+            assert!(
+                (ins.velocity()[0] - peak).abs() < 0.1 * peak,
+                "{:?}",
+                ins.velocity()
+            );
+        }
+    };
+
+    feed(&mut ins, Phase::Stance, 1.0, &mut stance);
+    const STEPS: usize = 4;
+    for _ in 0..STEPS {
+        feed(&mut ins, Phase::Swing, SWING_S, &mut swing);
+        feed(&mut ins, Phase::Stance, 0.5, &mut stance);
+    }
+    let before = ins.position();
+    feed(&mut ins, Phase::Stance, 2.0, &mut stance);
+    let after = ins.position();
+
+    // At rest the position no longer moves
+    for i in 0..3 {
+        assert!((after[i] - before[i]).abs() < 0.01, "{before:?} {after:?}");
+    }
+    // Distance walked, within a few percent, and no drift sideways or up
+    let walked = STEPS as f32 * step_length();
+    assert!(
+        (after[0] - walked).abs() < 0.05 * walked,
+        "{after:?} {walked}"
+    );
+    assert!(after[1].abs() < 0.05 && after[2].abs() < 0.05, "{after:?}");
+}
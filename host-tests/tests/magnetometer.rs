@@ -0,0 +1,93 @@
+use std::convert::Infallible;
+
+use bmi088::{
+    fusion::{magnetic_yaw, Ekf, EulerAngles, MagSettings, Quaternion},
+    magnetometer::{MagCalibration, MagSample, Magnetometer},
+};
+
+const HARD_IRON: [f32; 3] = [-15.0, 25.0, 5.0];
+
+/// Compass with a hard-iron offset that knows its own calibration
+struct Compass;
+
+impl Magnetometer for Compass {
+    type Error = Infallible;
+
+    async fn sample(&mut self) -> Result<MagSample, Infallible> {
+        Ok(MagSample::default())
+    }
+
+    fn calibration(&self) -> MagCalibration {
+        MagCalibration {
+            hard_iron: HARD_IRON,
+            ..Default::default()
+        }
+    }
+}
+
+/// Raw reading of a level board turned to `yaw` from magnetic north
+fn reading(yaw: f32, time_us: u32) -> MagSample {
+    // 20 µT north and 40 µT down
+    let (s, c) = yaw.sin_cos();
+    let board = [20.0 * c, -20.0 * s, -40.0];
+    MagSample {
+        xyz: [0, 1, 2].map(|i| board[i] + HARD_IRON[i]),
+        time_us,
+    }
+}
+
+#[test]
+fn reading_is_calibrated_before_use() {
+    let yaw = 30f32.to_radians();
+    let sample = reading(yaw, 1_000);
+    let level = Quaternion::IDENTITY;
+    let settings = MagSettings::default();
+
+    let fused = settings.yaw(&Compass, &sample, &level, 1_000).unwrap();
+    assert!((fused - yaw).abs() < 1e-4, "{fused}");
+    let uncorrected = magnetic_yaw(&level, sample.xyz).unwrap();
+    assert!((uncorrected - yaw).abs() > 0.1, "{uncorrected}");
+}
+
+#[test]
+fn stale_reading_is_dropped() {
+    let settings = MagSettings {
+        max_age_us: 20_000,
+        ..Default::default()
+    };
+    let level = Quaternion::IDENTITY;
+    let yaw = |sample: &MagSample, imu_time_us| settings.yaw(&Compass, sample, &level, imu_time_us);
+
+    let sample = reading(0.5, 100_000);
+    assert!(yaw(&sample, 115_000).is_some());
+    // A reading slightly newer than the IMU sample is just as good
+    assert!(yaw(&sample, 95_000).is_some());
+    assert!(yaw(&sample, 125_000).is_none());
+    assert!(yaw(&sample, 75_000).is_none());
+
+    // Across the wrap of the µs clock
+    let sample = reading(0.5, u32::MAX - 5_000);
+    assert!(yaw(&sample, 5_000).is_some());
+    assert!(yaw(&sample, 30_000).is_none());
+}
+
+#[test]
+fn ekf_yaw_follows_fresh_readings_only() {
+    let settings = MagSettings::default();
+    let heading = 40f32.to_radians();
+    let mut ekf = Ekf::default();
+    let start = ekf.euler().yaw;
+
+    let stale = reading(heading, 0);
+    assert!(!ekf.correct_mag(&Compass, &stale, 1_000_000, &settings));
+    assert_eq!(ekf.euler().yaw, start);
+
+    for i in 0..50 {
+        let time_us = 1_000_000 + i * 10_000;
+        let fresh = reading(heading, time_us - 2_000);
+        assert!(ekf.correct_mag(&Compass, &fresh, time_us, &settings));
+    }
+    let EulerAngles { roll, pitch, yaw } = ekf.euler();
+    assert!((yaw - heading).abs() < 1f32.to_radians(), "{yaw}");
+    assert!(roll.abs() < 1e-3 && pitch.abs() < 1e-3);
+}
@@ -0,0 +1,79 @@
+use bmi088::{
+    fusion::{Preintegrator, STANDARD_GRAVITY},
+    sample::{AccelSample, GyroSample},
+};
+
+const RATE_DPS: f32 = 90.0;
+const GYRO_PERIOD_US: u32 = 2500;
+const ACCEL_PERIOD_US: u32 = 10_000;
+
+fn gyro() -> GyroSample {
+    GyroSample {
+        xyz: [0.0, 0.0, RATE_DPS],
+    }
+}
+
+fn accel() -> AccelSample {
+    AccelSample {
+        xyz: [1.0, 0.0, 0.5],
+        sensor_time: 0,
+    }
+}
+
+#[test]
+fn empty_increment_is_none() {
+    let mut pre = Preintegrator::new();
+    pre.push_gyro(&gyro(), 0);
+    pre.push_accel(&accel(), 0);
+    pre.push_gyro(&gyro(), GYRO_PERIOD_US);
+    // A segment has started but nothing is integrated yet
+    assert_eq!(pre.take(), None);
+
+    pre.push_accel(&accel(), ACCEL_PERIOD_US);
+    assert!(pre.take().is_some());
+    assert_eq!(pre.take(), None);
+}
+
+#[test]
+fn constant_rate_rotation_matches_closed_form() {
+    let mut pre = Preintegrator::new();
+    pre.push_gyro(&gyro(), 0);
+    pre.push_accel(&accel(), 0);
+    pre.push_gyro(&gyro(), GYRO_PERIOD_US);
+    assert_eq!(pre.take(), None);
+
+    // 50 ms of gyroscope at 400 Hz and accelerometer at 100 Hz
+    let start_us = GYRO_PERIOD_US;
+    let end_us = start_us + 50_000;
+    let mut t = start_us;
+    while t < end_us {
+        t += GYRO_PERIOD_US;
+        if t.is_multiple_of(ACCEL_PERIOD_US) {
+            pre.push_accel(&accel(), t);
+        }
+        pre.push_gyro(&gyro(), t);
+    }
+    let increment = pre.take().unwrap();
+    assert_eq!((increment.start_us, increment.end_us), (start_us, end_us));
+    assert!((increment.dt - 0.05).abs() < 1e-6);
+
+    // Rotation about Z at ω: a board-fixed force f turns into
+    // R(ωt) f in the start frame, integrating to
+    // [fx sin(ωT) - fy (1 - cos(ωT)), fx (1 - cos(ωT)) + fy sin(ωT), fz ωT] / ω.
+    // The second-order compensation leaves a residual of about f T (ωT)² / 6.
+    let omega = RATE_DPS.to_radians();
+    let angle = omega * increment.dt;
+    assert!(increment.delta_angle[0].abs() < 1e-6);
+    assert!(increment.delta_angle[1].abs() < 1e-6);
+    assert!((increment.delta_angle[2] - angle).abs() < 1e-5);
+
+    let [fx, _, fz] = accel().xyz.map(|a| a * STANDARD_GRAVITY);
+    let expected = [
+        fx * angle.sin() / omega,
+        fx * (1.0 - angle.cos()) / omega,
+        fz * increment.dt,
+    ];
+    for (dv, e) in increment.delta_velocity.into_iter().zip(expected) {
+        assert!((dv - e).abs() < 1e-3, "{dv} != {e}");
+    }
+}
@@ -0,0 +1,98 @@
+use bmi088::{
+    gyro_impl::GyroscopeRange,
+    interface::{BusStats, RetryPolicy},
+    register_address::GyroRegisters,
+    Bmi088, Error,
+};
+use bmi088_host_tests::{block_on, Die, FakeBus, NoDelay};
+
+#[test]
+fn soft_reset_is_not_retried() {
+    let bus = FakeBus::new(Die::Gyroscope);
+    let mut gyro =
+        Bmi088::new_gyro_with_i2c(bus.clone(), 0x68).with_retry(NoDelay, RetryPolicy::default());
+
+    bus.chip().fail = 1;
+    assert!(block_on(gyro.soft_reset()).is_err());
+    assert_eq!(gyro.bus_stats().retries, 0);
+    assert_eq!(gyro.bus_stats().failures, 1);
+
+    // Configuration writes are still retried
+    bus.chip().fail = 1;
+    block_on(gyro.set_range(GyroscopeRange::Scale250)).unwrap();
+    assert_eq!(gyro.bus_stats().retries, 1);
+    assert_eq!(bus.register(GyroRegisters::RANGE as u8), 0x03);
+}
+
+#[test]
+fn transient_failure_is_retried() {
+    let bus = FakeBus::new(Die::Gyroscope);
+    let mut gyro =
+        Bmi088::new_gyro_with_i2c(bus.clone(), 0x68).with_retry(NoDelay, RetryPolicy::default());
+
+    bus.chip().fail = 2;
+    assert_eq!(block_on(gyro.chip_id()).unwrap(), 0x0F);
+    assert_eq!(
+        *gyro.bus_stats(),
+        BusStats {
+            transfers: 1,
+            retries: 2,
+            timeouts: 0,
+            failures: 0,
+        }
+    );
+}
+
+#[test]
+fn gives_up_after_max_attempts() {
+    let bus = FakeBus::new(Die::Gyroscope);
+    let policy = RetryPolicy {
+        max_attempts: 4,
+        ..Default::default()
+    };
+    let mut gyro = Bmi088::new_gyro_with_i2c(bus.clone(), 0x68).with_retry(NoDelay, policy);
+
+    bus.chip().fail = 5;
+    let err = block_on(gyro.chip_id()).unwrap_err();
+    assert!(matches!(err, Error::IOError { .. }));
+    assert_eq!(gyro.bus_stats().retries, 3);
+    assert_eq!(gyro.bus_stats().failures, 1);
+    // Exactly four attempts reached the bus
+    assert_eq!(bus.chip().fail, 1);
+
+    // Reads can be left to the caller
+    let mut gyro = Bmi088::new_gyro_with_i2c(bus.clone(), 0x68).with_retry(
+        NoDelay,
+        RetryPolicy {
+            retry_reads: false,
+            ..policy
+        },
+    );
+    assert!(block_on(gyro.chip_id()).is_err());
+    assert_eq!(gyro.bus_stats().retries, 0);
+    assert_eq!(bus.chip().fail, 0);
+}
+
+#[test]
+fn stuck_transfer_times_out() {
+    let bus = FakeBus::new(Die::Gyroscope);
+    let policy = RetryPolicy {
+        max_attempts: 2,
+        timeout_us: Some(1_000),
+        ..Default::default()
+    };
+    let mut gyro = Bmi088::new_gyro_with_i2c(bus.clone(), 0x68).with_retry(NoDelay, policy);
+
+    bus.chip().hang = true;
+    let err = block_on(gyro.chip_id()).unwrap_err();
+    let Error::Timeout(context) = err else {
+        panic!("{err:?}");
+    };
+    assert_eq!(context.register, GyroRegisters::CHIP_ID as u8);
+    assert_eq!(gyro.bus_stats().timeouts, 2);
+    assert_eq!(gyro.bus_stats().retries, 1);
+    assert_eq!(gyro.bus_stats().failures, 1);
+
+    bus.chip().hang = false;
+    assert_eq!(block_on(gyro.chip_id()).unwrap(), 0x0F);
+}
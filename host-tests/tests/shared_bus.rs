@@ -0,0 +1,128 @@
+use std::{
+    cell::Cell,
+    convert::Infallible,
+    future::{poll_fn, Future},
+    rc::Rc,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+};
+
+use bmi088::shared_bus::{SharedSpiBus, SharedSpiDevice};
+use bmi088_host_tests::NoDelay;
+use embedded_hal::digital::{ErrorType as PinErrorType, OutputPin};
+use embedded_hal_async::spi::{ErrorType, SpiBus, SpiDevice};
+
+/// Chip-select line, `true` while high
+#[derive(Clone)]
+struct Pin(Rc<Cell<bool>>);
+
+impl PinErrorType for Pin {
+    type Error = Infallible;
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Infallible> {
+        self.0.set(false);
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Infallible> {
+        self.0.set(true);
+        Ok(())
+    }
+}
+
+/// Bus whose every transfer waits once, like a DMA transfer
+struct SlowBus;
+
+async fn yield_once() {
+    let mut yielded = false;
+    poll_fn(|cx| {
+        if yielded {
+            return Poll::Ready(());
+        }
+        yielded = true;
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    })
+    .await
+}
+
+impl ErrorType for SlowBus {
+    type Error = Infallible;
+}
+
+impl SpiBus for SlowBus {
+    async fn read(&mut self, _words: &mut [u8]) -> Result<(), Infallible> {
+        yield_once().await;
+        Ok(())
+    }
+
+    async fn write(&mut self, _words: &[u8]) -> Result<(), Infallible> {
+        yield_once().await;
+        Ok(())
+    }
+
+    async fn transfer(&mut self, _read: &mut [u8], _write: &[u8]) -> Result<(), Infallible> {
+        yield_once().await;
+        Ok(())
+    }
+
+    async fn transfer_in_place(&mut self, _words: &mut [u8]) -> Result<(), Infallible> {
+        yield_once().await;
+        Ok(())
+    }
+
+    async fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+struct CountingWaker(AtomicUsize);
+
+impl Wake for CountingWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[test]
+fn abandoned_transaction_deselects_and_wakes_waiter() {
+    let bus = SharedSpiBus::new(SlowBus, NoDelay);
+    let (acc_cs, gyro_cs) = (Pin(Rc::new(Cell::new(true))), Pin(Rc::new(Cell::new(true))));
+    let mut acc = SharedSpiDevice::new(&bus, acc_cs.clone());
+    let mut gyro = SharedSpiDevice::new(&bus, gyro_cs.clone());
+
+    let acc_waker = Waker::noop();
+    let gyro_wakes = Arc::new(CountingWaker(AtomicUsize::new(0)));
+    let gyro_waker = Waker::from(gyro_wakes.clone());
+
+    let mut acc_write = Box::pin(acc.write(&[0x7E, 0xB6]));
+    assert!(acc_write
+        .as_mut()
+        .poll(&mut Context::from_waker(acc_waker))
+        .is_pending());
+    assert!(!acc_cs.0.get());
+
+    let mut gyro_write = Box::pin(gyro.write(&[0x14, 0xB6]));
+    let mut gyro_cx = Context::from_waker(&gyro_waker);
+    for _ in 0..3 {
+        assert!(gyro_write.as_mut().poll(&mut gyro_cx).is_pending());
+    }
+    // Parked, not spinning
+    assert_eq!(gyro_wakes.0.load(Ordering::Relaxed), 0);
+    assert!(gyro_cs.0.get());
+
+    // Abandoned halfway, e.g. by a timeout
+    drop(acc_write);
+    assert!(acc_cs.0.get());
+    assert_eq!(gyro_wakes.0.load(Ordering::Relaxed), 1);
+
+    assert!(gyro_write.as_mut().poll(&mut gyro_cx).is_pending());
+    assert!(!gyro_cs.0.get() && acc_cs.0.get());
+    assert!(gyro_write.as_mut().poll(&mut gyro_cx).is_ready());
+    assert!(gyro_cs.0.get());
+}
@@ -0,0 +1,61 @@
+use std::f32::consts::PI;
+
+use bmi088::spectrum::{SpectrumAnalyzer, Window};
+
+const RATE_HZ: f32 = 1600.0;
+const N: usize = 1024;
+
+/// Window with the largest frequency error in bins and the largest relative
+/// amplitude error for a tone anywhere between two bins
+const WINDOWS: [(Window, f32, f32); 5] = [
+    (Window::Rectangular, 0.2, 0.08),
+    (Window::Hann, 0.05, 0.02),
+    (Window::Hamming, 0.05, 0.01),
+    (Window::Blackman, 0.05, 0.005),
+    (Window::FlatTop, 0.2, 0.001),
+];
+
+/// Sinusoid with a DC offset on X, inverted on Z, nothing on Y
+fn tone(hz: f32, amplitude: f32) -> SpectrumAnalyzer<N> {
+    let mut analyzer = SpectrumAnalyzer::new(RATE_HZ);
+    for n in 0..N {
+        let x = 0.3 + amplitude * (2.0 * PI * hz * n as f32 / RATE_HZ).sin();
+        assert_eq!(analyzer.push([x, 0.0, -x]), n == N - 1);
+    }
+    analyzer
+}
+
+#[test]
+fn peak_frequency_and_amplitude_per_window() {
+    let resolution = RATE_HZ / N as f32;
+    for offset in [0.0, 0.25, 0.5] {
+        let hz = (64.0 + offset) * resolution;
+        let analyzer = tone(hz, 2.0);
+        for (window, max_bins, max_amplitude) in WINDOWS {
+            for axis in [0, 2] {
+                let spectrum = analyzer.spectrum(axis, window).unwrap();
+                let [peak, none] = spectrum.peaks::<2>(10.0, 700.0);
+                let peak = peak.unwrap();
+                let bins = (peak.frequency_hz - hz) / resolution;
+                assert!(bins.abs() < max_bins, "{window:?} {offset}: {bins} bins");
+                let error = peak.amplitude / 2.0 - 1.0;
+                assert!(error.abs() < max_amplitude, "{window:?} {offset}: {error}");
+                // Sidelobes stay far below the tone
+                assert!(
+                    none.is_none_or(|p| p.amplitude < 0.1),
+                    "{window:?} {none:?}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn empty_axis_has_no_peak() {
+    let analyzer = tone(100.0, 2.0);
+    let spectrum = analyzer.spectrum(1, Window::Hann).unwrap();
+    assert_eq!(spectrum.peaks::<1>(10.0, 700.0), [None]);
+    assert!(SpectrumAnalyzer::<N>::new(RATE_HZ)
+        .spectrum(0, Window::Hann)
+        .is_none());
+}
@@ -1,9 +1,25 @@
+use embedded_hal_async::delay::DelayNs;
+
 use crate::{
-    interface::{AsyncReadData, AsyncWriteData, I2cInterface, SpiInterface},
+    calibration::{AccelCalibration, Moments, TempModel},
+    config::{AccelConfig, ShadowCheck},
+    filter::SampleFilter,
+    inclinometer::{mean_std, Tilt},
+    interface::{
+        AsyncReadData, AsyncWriteData, BusStats, I2cInterface, RetryInterface, RetryPolicy,
+        SpiInterface,
+    },
+    math::white_noise,
+    orientation::Mounting,
     register_address::{acc, AccRegisters},
-    Bmi088, Error,
+    sample::AccelSample,
+    spectrum::SpectrumAnalyzer,
+    Bmi088, Error, Sensor,
 };
 
+/// Typical output noise density from the datasheet in g/√Hz
+pub(crate) const NOISE_DENSITY: f32 = 175e-6;
+
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 #[repr(u8)]
@@ -20,6 +36,16 @@ pub enum AccelerometerRange {
 }
 
 impl AccelerometerRange {
+    pub(crate) const fn from_register(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(AccelerometerRange::Scale3g),
+            0x01 => Some(AccelerometerRange::Scale6g),
+            0x02 => Some(AccelerometerRange::Scale12g),
+            0x03 => Some(AccelerometerRange::Scale24g),
+            _ => None,
+        }
+    }
+
     pub(crate) const fn multiplier(&self) -> f32 {
         match self {
             AccelerometerRange::Scale3g => {
@@ -53,6 +79,126 @@ impl AccelerometerRange {
 pub struct Accelerometer<DI> {
     iface: DI,
     range: AccelerometerRange,
+    shadow: AccelConfig,
+    verify_writes: bool,
+    auto_restore: bool,
+    mounting: Mounting,
+    calibration: AccelCalibration,
+    temp_model: Option<TempModel>,
+    temperature: Option<f32>,
+}
+
+impl<DI> Accelerometer<DI> {
+    pub(crate) fn from_iface(iface: DI) -> Self {
+        Accelerometer {
+            iface,
+            range: Default::default(),
+            shadow: Default::default(),
+            verify_writes: false,
+            auto_restore: false,
+            mounting: Default::default(),
+            calibration: Default::default(),
+            temp_model: None,
+            temperature: None,
+        }
+    }
+
+    /// Read back every configuration write and fail with
+    /// [`Error::VerifyFailed`] if the chip holds a different value.
+    ///
+    /// The power registers only read back correctly after the mode change
+    /// settled, so they are verified by [`Accelerometer::apply_config`] after
+    /// its delay and not at all by [`Accelerometer::set_pwr_save`] and
+    /// [`Accelerometer::enable_acc`].
+    pub fn set_write_verify(&mut self, enabled: bool) {
+        self.verify_writes = enabled;
+    }
+
+    /// Re-apply the shadow configuration when [`Accelerometer::check_shadow`]
+    /// finds that the chip has been reset.
+    pub fn set_auto_restore(&mut self, enabled: bool) {
+        self.auto_restore = enabled;
+    }
+
+    /// Retry failed bus transfers according to `policy`
+    ///
+    /// `delay` is used for the backoff between attempts and for the
+    /// per-attempt timeout.
+    pub fn with_retry<D: DelayNs>(
+        self,
+        delay: D,
+        policy: RetryPolicy,
+    ) -> Accelerometer<RetryInterface<DI, D>> {
+        self.map_iface(|iface| RetryInterface::new(iface, delay, policy))
+    }
+
+    fn map_iface<NI>(self, f: impl FnOnce(DI) -> NI) -> Accelerometer<NI> {
+        Accelerometer {
+            iface: f(self.iface),
+            range: self.range,
+            shadow: self.shadow,
+            verify_writes: self.verify_writes,
+            auto_restore: self.auto_restore,
+            mounting: self.mounting,
+            calibration: self.calibration,
+            temp_model: self.temp_model,
+            temperature: self.temperature,
+        }
+    }
+
+    /// Rotate every sample from the chip frame into the board frame
+    pub fn set_mounting(&mut self, mounting: Mounting) {
+        self.mounting = mounting;
+    }
+
+    pub fn mounting(&self) -> &Mounting {
+        &self.mounting
+    }
+
+    /// Offset, scale and misalignment correction applied by
+    /// [`Accelerometer::xyz`]
+    pub fn set_calibration(&mut self, calibration: AccelCalibration) {
+        self.calibration = calibration;
+    }
+
+    pub fn calibration(&self) -> &AccelCalibration {
+        &self.calibration
+    }
+
+    /// Temperature model applied before the other corrections
+    ///
+    /// It is evaluated at the last valid die temperature, or at the model's
+    /// reference temperature until one has been read.
+    pub fn set_temp_model(&mut self, model: Option<TempModel>) {
+        self.temp_model = model;
+    }
+
+    pub fn temp_model(&self) -> Option<&TempModel> {
+        self.temp_model.as_ref()
+    }
+
+    /// Last valid reading of [`Accelerometer::temperature`]
+    pub fn last_temperature(&self) -> Option<f32> {
+        self.temperature
+    }
+
+    fn compensate(&self, v: [f32; 3]) -> [f32; 3] {
+        match &self.temp_model {
+            Some(model) => model.apply(v, self.temperature.unwrap_or(model.t_ref)),
+            None => v,
+        }
+    }
+
+    /// Chip-frame g to calibrated board-frame g
+    fn correct(&self, acc: [f32; 3]) -> [f32; 3] {
+        let acc = self.calibration.apply(self.compensate(acc));
+        self.mounting.apply(acc)
+    }
+
+    /// Configuration the driver has written to the chip
+    pub fn shadow_config(&self) -> &AccelConfig {
+        &self.shadow
+    }
 }
 
 impl<SPI> Bmi088<SpiInterface<SPI>> {
@@ -62,23 +208,37 @@ impl<SPI> Bmi088<SpiInterface<SPI>> {
     /// on the CSB1 pin, so change the accelerometer to SPI mode in the
     /// initialization phase, the user could perform a dummy SPI read operation
     pub fn new_acc_with_spi(spi: SPI) -> Accelerometer<SpiInterface<SPI>> {
-        Accelerometer {
-            iface: SpiInterface {
-                spi,
-                has_dummy_byte: true,
-            },
-            range: Default::default(),
-        }
+        Accelerometer::from_iface(SpiInterface {
+            spi,
+            has_dummy_byte: true,
+            sensor: Sensor::Accelerometer,
+        })
     }
 }
 
 impl<I2C> Bmi088<I2cInterface<I2C>> {
     /// Create new instance of the BMI088 accelerometer communicating with I2C.
     pub fn new_acc_with_i2c(i2c: I2C, address: u8) -> Accelerometer<I2cInterface<I2C>> {
-        Accelerometer {
-            iface: I2cInterface { i2c, address },
-            range: Default::default(),
-        }
+        Accelerometer::from_iface(I2cInterface {
+            i2c,
+            address,
+            sensor: Sensor::Accelerometer,
+        })
+    }
+}
+
+impl<DI, D> Accelerometer<RetryInterface<DI, D>> {
+    /// Transfer counters of the retry layer
+    pub fn bus_stats(&self) -> &BusStats {
+        &self.iface.stats
+    }
+
+    pub fn reset_bus_stats(&mut self) {
+        self.iface.stats = Default::default();
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.iface.policy = policy;
     }
 }
 
@@ -94,34 +254,69 @@ where
     pub async fn soft_reset(&mut self) -> Result<(), Error<E>> {
         self.iface
             .write_register(AccRegisters::SOFTRESET as _, 0xB6)
-            .await
+            .await?;
+        self.shadow = Default::default();
+        self.range = Default::default();
+        Ok(())
     }
 
     pub async fn set_pwr_save(&mut self, mode: u8) -> Result<(), Error<E>> {
-        self.iface
-            .write_register(AccRegisters::PWR_CONF as _, mode)
+        self.write_config_register(AccRegisters::PWR_CONF, mode)
             .await
     }
 
     pub async fn set_conf(&mut self, conf: u8) -> Result<(), Error<E>> {
-        self.iface
-            .write_register(AccRegisters::CONF as _, conf)
-            .await
+        self.write_config_register(AccRegisters::CONF, conf).await
     }
 
     pub async fn set_range(&mut self, range: AccelerometerRange) -> Result<(), Error<E>> {
+        self.write_config_register(AccRegisters::RANGE, range as u8)
+            .await?;
         self.range = range;
-        self.iface
-            .write_register(AccRegisters::RANGE as _, range as u8)
-            .await
+        Ok(())
     }
 
     pub async fn enable_acc(&mut self) -> Result<(), Error<E>> {
-        self.iface
-            .write_register(AccRegisters::PWR_CTRL as _, 0x04)
+        self.write_config_register(AccRegisters::PWR_CTRL, 0x04)
             .await
     }
 
+    async fn write_config_register(
+        &mut self,
+        reg: AccRegisters,
+        value: u8,
+    ) -> Result<(), Error<E>> {
+        self.iface.write_register(reg as _, value).await?;
+        // Power registers are verified once the mode change settled
+        if !matches!(reg, AccRegisters::PWR_CONF | AccRegisters::PWR_CTRL) {
+            self.verify_register(reg, value).await?;
+        }
+        match reg {
+            AccRegisters::CONF => self.shadow.conf = value,
+            AccRegisters::RANGE => self.shadow.range = value,
+            AccRegisters::PWR_CONF => self.shadow.pwr_conf = value,
+            AccRegisters::PWR_CTRL => self.shadow.pwr_ctrl = value,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn verify_register(&mut self, reg: AccRegisters, value: u8) -> Result<(), Error<E>> {
+        if !self.verify_writes {
+            return Ok(());
+        }
+        let read = self.iface.read_register(reg as _).await?;
+        if read != value {
+            return Err(Error::VerifyFailed {
+                sensor: Sensor::Accelerometer,
+                reg: reg as u8,
+                wrote: value,
+                read,
+            });
+        }
+        Ok(())
+    }
+
     /// Get chip ID
     pub async fn chip_id(&mut self) -> Result<u8, Error<E>> {
         self.iface.read_register(AccRegisters::CHIP_ID as _).await
@@ -132,10 +327,169 @@ where
         self.iface.read_data(&mut data).await?;
         let temperature = (((data[2] as i8) as i16) << 3) | (((data[3] as u16) >> 5) as i16);
         let temperature = temperature as f32 * 0.125 + 23.0;
+        if (-40.0..=125.0).contains(&temperature) {
+            self.temperature = Some(temperature);
+        }
         Ok(temperature)
     }
 
+    /// Raw sample in the board frame
     pub async fn brust_read_xyz(&mut self) -> Result<(i16, i16, i16), Error<E>> {
+        let raw = self.read_chip_xyz().await?;
+        let [x, y, z] = self.mounting.apply_raw(raw);
+        Ok((x, y, z))
+    }
+
+    /// Calibrated acceleration in g in the board frame
+    pub async fn xyz(&mut self) -> Result<(f32, f32, f32), Error<E>> {
+        let acc = self.read_chip_g().await?;
+        let [x, y, z] = self.correct(acc);
+        Ok((x, y, z))
+    }
+
+    /// Calibrated acceleration as [`Self::xyz`] with its sensor time
+    ///
+    /// Data and sensor time are read in one burst, so the timestamp belongs
+    /// to the sample.
+    pub async fn sample(&mut self) -> Result<AccelSample, Error<E>> {
+        let status = self.iface.read_register(AccRegisters::STATUS as u8).await?;
+        if !acc::Status::DRDY.is_set(status) {
+            return Err(Error::NoDrdy);
+        }
+        let mut data = [0; 11];
+        data[0] = AccRegisters::X_LSB as u8 | 0x80;
+        self.iface.read_data(&mut data).await?;
+        let raw = [2, 4, 6].map(|i| i16::from_le_bytes([data[i], data[i + 1]]));
+        let acc = raw.map(|v| v as f32 * self.range.multiplier());
+        Ok(AccelSample {
+            xyz: self.correct(acc),
+            sensor_time: u32::from_le_bytes([data[8], data[9], data[10], 0x00]),
+        })
+    }
+
+    /// [`Self::sample`] passed through `filter`, e.g. a
+    /// [`FilterChain`](crate::filter::FilterChain)
+    ///
+    /// The filter's sample rate follows the ODR of the shadow configuration.
+    pub async fn filtered<F: SampleFilter>(
+        &mut self,
+        filter: &mut F,
+    ) -> Result<AccelSample, Error<E>> {
+        let sample = self.sample().await?;
+        if let Some(odr) = self.shadow.odr_hz() {
+            filter.set_sample_rate(odr);
+        }
+        Ok(AccelSample {
+            xyz: filter.process(sample.xyz),
+            ..sample
+        })
+    }
+
+    /// Fill `analyzer` with consecutive samples as [`Self::sample`] at the
+    /// configured ODR
+    ///
+    /// The analyzer is cleared and takes the ODR of the shadow configuration
+    /// as its sample rate. The data-ready flag is polled four times per
+    /// sample period, so the bus has to keep up with the ODR for the samples
+    /// to be consecutive.
+    pub async fn capture<const N: usize>(
+        &mut self,
+        analyzer: &mut SpectrumAnalyzer<N>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error<E>> {
+        let odr = self.shadow.odr_hz().unwrap_or(100.0);
+        analyzer.set_sample_rate(odr);
+        analyzer.clear();
+        let interval_us = (250_000.0 / odr) as u32;
+        let mut misses = 0usize;
+        while !analyzer.is_full() {
+            match self.sample().await {
+                Ok(sample) => {
+                    analyzer.push(sample.xyz);
+                }
+                Err(Error::NoDrdy) if misses < 8 * N => misses += 1,
+                Err(e) => return Err(e),
+            }
+            delay.delay_us(interval_us).await;
+        }
+        Ok(())
+    }
+
+    /// Average `samples` chip-frame readings in g, temperature-compensated
+    /// but not calibrated
+    ///
+    /// Polls every `interval_us` and skips polls without new data. Use this
+    /// to collect the positions of an
+    /// [`AccelCalibrator`](crate::calibration::AccelCalibrator).
+    pub async fn read_averaged(
+        &mut self,
+        samples: u16,
+        interval_us: u32,
+        delay: &mut impl DelayNs,
+    ) -> Result<[f32; 3], Error<E>> {
+        let moments = self.collect(samples, interval_us, delay, false).await?;
+        Ok(moments.mean())
+    }
+
+    /// Average `samples` calibrated readings and derive roll and pitch
+    ///
+    /// Polls every `interval_us` and skips polls without new data. The
+    /// uncertainty follows from the configured range and bandwidth, i.e.
+    /// the oversampling setting, and the averaging time. It is raised to the
+    /// observed scatter if the device vibrates.
+    pub async fn tilt(
+        &mut self,
+        samples: u16,
+        interval_us: u32,
+        delay: &mut impl DelayNs,
+    ) -> Result<Tilt, Error<E>> {
+        let moments = self.collect(samples, interval_us, delay, true).await?;
+        let bandwidth = self.shadow.bandwidth_hz().unwrap_or(280.0);
+        let single = white_noise(NOISE_DENSITY, bandwidth, self.range.multiplier());
+        let duration = f32::from(samples) * interval_us as f32 * 1e-6;
+        let model = mean_std(single, NOISE_DENSITY, samples, duration);
+        let sqrt_n = libm::sqrtf(f32::from(samples.max(1)));
+        let std = moments
+            .std_dev()
+            .iter()
+            .fold(model, |m, s| m.max(s / sqrt_n));
+        Ok(Tilt::from_gravity(moments.mean(), std, samples))
+    }
+
+    async fn collect(
+        &mut self,
+        samples: u16,
+        interval_us: u32,
+        delay: &mut impl DelayNs,
+        calibrated: bool,
+    ) -> Result<Moments, Error<E>> {
+        let mut moments = Moments::default();
+        let mut collected = 0;
+        let mut misses = 0u32;
+        while collected < samples {
+            match self.read_chip_g().await {
+                Ok(acc) => {
+                    moments.push(if calibrated {
+                        self.correct(acc)
+                    } else {
+                        self.compensate(acc)
+                    });
+                    collected += 1;
+                }
+                Err(Error::NoDrdy) if misses < 4 * u32::from(samples) => misses += 1,
+                Err(e) => return Err(e),
+            }
+            delay.delay_us(interval_us).await;
+        }
+        Ok(moments)
+    }
+
+    async fn read_chip_g(&mut self) -> Result<[f32; 3], Error<E>> {
+        let raw = self.read_chip_xyz().await?;
+        Ok(raw.map(|v| v as f32 * self.range.multiplier()))
+    }
+
+    async fn read_chip_xyz(&mut self) -> Result<[i16; 3], Error<E>> {
         let status = self.iface.read_register(AccRegisters::STATUS as u8).await?;
         if !acc::Status::DRDY.is_set(status) {
             return Err(Error::NoDrdy);
@@ -145,15 +499,92 @@ where
         let x_raw = i16::from_le_bytes([data[2], data[3]]);
         let y_raw = i16::from_le_bytes([data[4], data[5]]);
         let z_raw = i16::from_le_bytes([data[6], data[7]]);
-        Ok((x_raw, y_raw, z_raw))
+        Ok([x_raw, y_raw, z_raw])
     }
 
-    pub async fn xyz(&mut self) -> Result<(f32, f32, f32), Error<E>> {
-        let (x, y, z) = self.brust_read_xyz().await?;
-        let x = x as f32 * self.range.multiplier();
-        let y = y as f32 * self.range.multiplier();
-        let z = z as f32 * self.range.multiplier();
-        Ok((x, y, z))
+    /// Read the configuration registers
+    ///
+    /// The cached range used by [`Self::xyz`] is refreshed from the chip.
+    pub async fn read_config(&mut self) -> Result<AccelConfig, Error<E>> {
+        let config = AccelConfig {
+            conf: self.iface.read_register(AccRegisters::CONF as _).await?,
+            range: self.iface.read_register(AccRegisters::RANGE as _).await?,
+            pwr_conf: self
+                .iface
+                .read_register(AccRegisters::PWR_CONF as _)
+                .await?,
+            pwr_ctrl: self
+                .iface
+                .read_register(AccRegisters::PWR_CTRL as _)
+                .await?,
+        };
+        if let Some(range) = config.accel_range() {
+            self.range = range;
+        }
+        Ok(config)
+    }
+
+    /// Write the configuration registers
+    ///
+    /// The accelerometer needs 1 ms after a power mode change before it
+    /// accepts further configuration. A value the chip does not accept is
+    /// rejected with [`Error::InvalidConfig`] before anything is written.
+    pub async fn apply_config(
+        &mut self,
+        config: &AccelConfig,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), Error<E>> {
+        let range = config
+            .validate()
+            .map_err(|(reg, value)| Error::InvalidConfig {
+                sensor: Sensor::Accelerometer,
+                reg: reg as u8,
+                value,
+            })?;
+        self.write_config_register(AccRegisters::PWR_CTRL, config.pwr_ctrl)
+            .await?;
+        self.set_pwr_save(config.pwr_conf).await?;
+        delay.delay_ms(1).await;
+        self.verify_register(AccRegisters::PWR_CTRL, config.pwr_ctrl)
+            .await?;
+        self.verify_register(AccRegisters::PWR_CONF, config.pwr_conf)
+            .await?;
+        self.set_conf(config.conf).await?;
+        self.set_range(range).await
+    }
+
+    /// Adopt the chip's current configuration as the shadow
+    ///
+    /// Use this instead of [`Self::apply_config`] when attaching to a chip
+    /// that may still be configured, e.g. after an MCU reset.
+    pub async fn sync_shadow(&mut self) -> Result<AccelConfig, Error<E>> {
+        let config = self.read_config().await?;
+        self.shadow = config;
+        Ok(config)
+    }
+
+    /// Compare the chip's configuration with the shadow copy
+    ///
+    /// A mismatch means the accelerometer has been reset behind the driver's
+    /// back. With auto restore enabled the shadow configuration is written
+    /// again.
+    ///
+    /// The shadow starts out with the reset values. Run [`Self::apply_config`]
+    /// or [`Self::sync_shadow`] first, otherwise a chip that kept its
+    /// configuration while only the MCU restarted is taken for reset.
+    pub async fn check_shadow(
+        &mut self,
+        delay: &mut impl DelayNs,
+    ) -> Result<ShadowCheck, Error<E>> {
+        if self.read_config().await? == self.shadow {
+            return Ok(ShadowCheck::Consistent);
+        }
+        if !self.auto_restore {
+            return Ok(ShadowCheck::ResetDetected);
+        }
+        let shadow = self.shadow;
+        self.apply_config(&shadow, delay).await?;
+        Ok(ShadowCheck::Restored)
     }
 
     pub async fn sensor_time_us(&mut self) -> Result<u32, Error<E>> {
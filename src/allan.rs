@@ -0,0 +1,163 @@
+//! Allan deviation of recorded sensor data
+//!
+//! Log a few hours of samples at rest at a fixed ODR and temperature, e.g.
+//! [`GyroSample::xyz`](crate::sample::GyroSample) in °/s or
+//! [`AccelSample::xyz`](crate::sample::AccelSample) in g, and pass them to
+//! [`AllanCurve::compute`] on the host. [`AllanCurve::noise`] extracts the
+//! figures used to tune the Kalman filters.
+
+/// Allan deviation at one cluster time
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AllanPoint {
+    /// Cluster time in s
+    pub tau_s: f32,
+    /// Deviation per axis in sample units
+    pub deviation: [f32; 3],
+    /// Overlapping clusters averaged
+    pub clusters: u32,
+}
+
+/// Noise figures read off an [`AllanCurve`]
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct NoiseFigures {
+    /// White noise as random walk in sample units times √s
+    ///
+    /// Angle random walk in °/√s for the gyroscope, multiply by 60 for
+    /// °/√h. Velocity random walk in g·√s for the accelerometer, which
+    /// equals the noise density in g/√Hz.
+    pub random_walk: [f32; 3],
+    /// Bias instability in sample units
+    pub bias_instability: [f32; 3],
+    /// Cluster time of the bias instability floor in s
+    pub bias_instability_tau_s: [f32; 3],
+}
+
+impl NoiseFigures {
+    /// [`Self::random_walk`] per √h, °/√h for the gyroscope
+    pub fn random_walk_per_sqrt_hour(&self) -> [f32; 3] {
+        self.random_walk.map(|n| n * 60.0)
+    }
+}
+
+/// Overlapping Allan deviation at up to `M` log-spaced cluster times
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AllanCurve<const M: usize> {
+    points: [AllanPoint; M],
+    len: usize,
+}
+
+impl<const M: usize> AllanCurve<M> {
+    /// Allan deviation of `samples` taken at `sample_rate_hz`
+    ///
+    /// Cluster sizes are spaced `points_per_decade` per decade from one
+    /// sample up to half the record, repeated sizes are skipped. Each point
+    /// takes one pass over the data.
+    pub fn compute(samples: &[[f32; 3]], sample_rate_hz: f32, points_per_decade: u16) -> Self {
+        let mut curve = AllanCurve {
+            points: [AllanPoint::default(); M],
+            len: 0,
+        };
+        let max_cluster = samples.len() / 2;
+        let step = 1.0 / f64::from(points_per_decade.max(1));
+        let mut previous = 0;
+        let mut exponent = 0.0;
+        while curve.len < M {
+            let m = libm::round(libm::pow(10.0, exponent)) as usize;
+            exponent += step;
+            if m > max_cluster {
+                break;
+            }
+            if m == previous {
+                continue;
+            }
+            previous = m;
+            curve.points[curve.len] = AllanPoint {
+                tau_s: m as f32 / sample_rate_hz,
+                deviation: [0, 1, 2].map(|axis| deviation(samples, axis, m)),
+                clusters: (samples.len() + 1 - 2 * m) as u32,
+            };
+            curve.len += 1;
+        }
+        curve
+    }
+
+    pub fn points(&self) -> &[AllanPoint] {
+        &self.points[..self.len]
+    }
+
+    /// Random walk and bias instability of each axis
+    ///
+    /// The random walk is read at the shortest, best averaged cluster time
+    /// where the curve follows the -1/2 slope of white noise within 0.1
+    /// before its minimum, or else where it comes closest to that slope. The
+    /// bias instability is read from the minimum divided by 0.664. Both need
+    /// the record to reach past the minimum, typically a few hours for the
+    /// gyroscope.
+    pub fn noise(&self) -> NoiseFigures {
+        let mut figures = NoiseFigures::default();
+        let points = self.points();
+        if points.len() < 2 {
+            return figures;
+        }
+        for axis in 0..3 {
+            let Some((min, floor)) = points
+                .iter()
+                .enumerate()
+                .filter(|(_, p)| p.deviation[axis] > 0.0)
+                .min_by(|a, b| a.1.deviation[axis].total_cmp(&b.1.deviation[axis]))
+            else {
+                continue;
+            };
+            figures.bias_instability[axis] = floor.deviation[axis] / 0.664;
+            figures.bias_instability_tau_s[axis] = floor.tau_s;
+
+            let slope = |i: usize| {
+                let (a, b) = (&points[i], &points[i + 1]);
+                libm::logf(b.deviation[axis] / a.deviation[axis]) / libm::logf(b.tau_s / a.tau_s)
+            };
+            let segments =
+                || (0..min.max(1).min(points.len() - 1)).filter(|&i| slope(i).is_finite());
+            let white = segments()
+                .find(|&i| (slope(i) + 0.5).abs() <= 0.1)
+                .or_else(|| {
+                    segments()
+                        .min_by(|&a, &b| (slope(a) + 0.5).abs().total_cmp(&(slope(b) + 0.5).abs()))
+                });
+            if let Some(i) = white {
+                // σ(τ) = N / √τ on the white noise line
+                let p = &points[i];
+                figures.random_walk[axis] = p.deviation[axis] * libm::sqrtf(p.tau_s);
+            }
+        }
+        figures
+    }
+}
+
+/// Overlapping Allan deviation of `axis` for clusters of `m` samples
+///
+/// Sums over consecutive clusters slide along the record, so each cluster
+/// size costs one pass without storing the integrated signal.
+fn deviation(samples: &[[f32; 3]], axis: usize, m: usize) -> f32 {
+    let n = samples.len();
+    if m == 0 || n < 2 * m {
+        return 0.0;
+    }
+    let x = |i: usize| f64::from(samples[i][axis]);
+    let mut first: f64 = (0..m).map(x).sum();
+    let mut second: f64 = (m..2 * m).map(x).sum();
+    let mut sum_sq = 0.0;
+    let terms = n + 1 - 2 * m;
+    for k in 0..terms {
+        let d = second - first;
+        sum_sq += d * d;
+        if k + 1 < terms {
+            first += x(k + m) - x(k);
+            second += x(k + 2 * m) - x(k + m);
+        }
+    }
+    let m = m as f64;
+    libm::sqrt(sum_sq / (2.0 * m * m * terms as f64)) as f32
+}
@@ -0,0 +1,395 @@
+//! Sensor calibration
+//!
+//! Corrections estimated here are expressed in the chip frame and applied by
+//! the drivers before the mounting rotation, so they stay valid when the
+//! [`Mounting`](crate::orientation::Mounting) changes.
+//!
+//! A [`TempModel`] is applied first. The gyroscope bias and the
+//! [`AccelCalibration`] are estimated from and applied to
+//! temperature-compensated readings, so they only hold what the model leaves.
+//! Log the data for a [`TempFitter`] with no model installed.
+
+use crate::math::{mat3_vec, norm3, solve, symmetric_eigen, NormalEquations};
+
+/// Settings of a gyroscope bias calibration run
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct BiasCalibration {
+    /// Samples to collect
+    pub samples: u16,
+    /// Time between samples
+    pub interval_us: u32,
+    /// Largest per-axis standard deviation in °/s accepted as stillness
+    pub max_std_dev: f32,
+}
+
+impl Default for BiasCalibration {
+    fn default() -> Self {
+        Self {
+            samples: 500,
+            interval_us: 2_000,
+            max_std_dev: 1.0,
+        }
+    }
+}
+
+/// Result of a gyroscope bias calibration run
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct GyroBias {
+    /// Zero-rate offset in °/s, chip frame
+    pub bias: [f32; 3],
+    /// Per-axis standard deviation of the samples in °/s
+    pub std_dev: [f32; 3],
+    /// Samples the estimate is based on
+    pub samples: u16,
+}
+
+/// Running per-axis mean and variance (Welford)
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Moments {
+    count: u32,
+    mean: [f32; 3],
+    m2: [f32; 3],
+}
+
+impl Moments {
+    pub(crate) fn push(&mut self, v: [f32; 3]) {
+        self.count += 1;
+        let n = self.count as f32;
+        for ((x, mean), m2) in v.iter().zip(&mut self.mean).zip(&mut self.m2) {
+            let delta = x - *mean;
+            *mean += delta / n;
+            *m2 += delta * (x - *mean);
+        }
+    }
+
+    pub(crate) fn mean(&self) -> [f32; 3] {
+        self.mean
+    }
+
+    /// Sample standard deviation
+    pub(crate) fn std_dev(&self) -> [f32; 3] {
+        let n = self.count.saturating_sub(1).max(1) as f32;
+        self.m2.map(|m2| libm::sqrtf(m2 / n))
+    }
+}
+
+/// Why a calibration could not be produced
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum CalibrationError {
+    /// Fewer than six positions were collected
+    NotEnoughPositions,
+    /// Too few samples for the requested polynomial degree
+    NotEnoughSamples,
+    /// No room for another position
+    Full,
+    /// The positions do not span all axes (e.g. repeated orientations)
+    Singular,
+    /// The fitted surface is not an ellipsoid
+    NotEllipsoid,
+    /// RMS deviation from the expected magnitude after correction exceeds
+    /// the limit
+    ResidualTooLarge { rms: f32 },
+}
+
+/// Accelerometer offset, scale and cross-axis correction
+///
+/// A chip-frame sample `a` in g is corrected to `matrix * (a - offset)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AccelCalibration {
+    /// Zero-g offset in g
+    pub offset: [f32; 3],
+    /// Scale (diagonal) and misalignment (off-diagonal), symmetric
+    pub matrix: [[f32; 3]; 3],
+}
+
+impl Default for AccelCalibration {
+    fn default() -> Self {
+        Self {
+            offset: [0.0; 3],
+            matrix: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+        }
+    }
+}
+
+impl AccelCalibration {
+    pub fn apply(&self, v: [f32; 3]) -> [f32; 3] {
+        mat3_vec(&self.matrix, [0, 1, 2].map(|i| v[i] - self.offset[i]))
+    }
+}
+
+/// Outcome of [`AccelCalibrator::fit`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AccelFit {
+    pub calibration: AccelCalibration,
+    /// RMS deviation of the corrected positions from 1 g
+    pub residual_rms: f32,
+    /// Cross-axis terms were fitted (nine or more positions)
+    pub misalignment: bool,
+}
+
+/// Collects averaged static readings for a multi-position calibration
+///
+/// Hold the device still in each orientation and add the averaged chip-frame
+/// reading (see [`Accelerometer::read_averaged`]). Six positions, each axis
+/// pointing up and down, solve offset and scale. With nine or more distinct
+/// orientations, e.g. the six faces plus three tilted ones, the cross-axis
+/// misalignment is fitted too. The ellipsoid is fitted by least squares.
+///
+/// [`Accelerometer::read_averaged`]: crate::acc_impl::Accelerometer::read_averaged
+#[derive(Debug, Clone)]
+pub struct AccelCalibrator<const N: usize> {
+    positions: [[f32; 3]; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for AccelCalibrator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> AccelCalibrator<N> {
+    pub const fn new() -> Self {
+        Self {
+            positions: [[0.0; 3]; N],
+            len: 0,
+        }
+    }
+
+    /// Add the averaged reading of one orientation, in g
+    pub fn add_position(&mut self, reading: [f32; 3]) -> Result<(), CalibrationError> {
+        let slot = self
+            .positions
+            .get_mut(self.len)
+            .ok_or(CalibrationError::Full)?;
+        *slot = reading;
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn positions(&self) -> &[[f32; 3]] {
+        &self.positions[..self.len]
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Fit the calibration and check that the corrected positions lie within
+    /// `max_residual` g (RMS) of 1 g
+    ///
+    /// With exactly as many positions as unknowns the fit is exact and the
+    /// residual carries no information, so add spare positions when the
+    /// check matters.
+    pub fn fit(&self, max_residual: f32) -> Result<AccelFit, CalibrationError> {
+        let positions = self.positions();
+        let (calibration, misalignment) = fit_ellipsoid(positions)?;
+        let sum_sq: f32 = positions
+            .iter()
+            .map(|&p| {
+                let e = norm3(calibration.apply(p)) - 1.0;
+                e * e
+            })
+            .sum();
+        let residual_rms = libm::sqrtf(sum_sq / positions.len() as f32);
+        if residual_rms.is_nan() || residual_rms > max_residual {
+            return Err(CalibrationError::ResidualTooLarge { rms: residual_rms });
+        }
+        Ok(AccelFit {
+            calibration,
+            residual_rms,
+            misalignment,
+        })
+    }
+}
+
+/// Offset and symmetric matrix mapping `points` onto the unit sphere, and
+/// whether the cross-axis terms were fitted
+///
+/// Needs at least six points, nine or more also fit the cross-axis terms.
+pub(crate) fn fit_ellipsoid(
+    points: &[[f32; 3]],
+) -> Result<(AccelCalibration, bool), CalibrationError> {
+    if points.len() < 6 {
+        return Err(CalibrationError::NotEnoughPositions);
+    }
+    let misalignment = points.len() >= 9;
+    // x^T Q x + 2 u^T x = 1
+    let (q, u) = if misalignment {
+        let mut normal = NormalEquations::<9>::new();
+        for p in points {
+            let [x, y, z] = p.map(f64::from);
+            let row = [
+                x * x,
+                y * y,
+                z * z,
+                2.0 * x * y,
+                2.0 * x * z,
+                2.0 * y * z,
+                2.0 * x,
+                2.0 * y,
+                2.0 * z,
+            ];
+            normal.push(&row, 1.0);
+        }
+        let [a, b, c, d, e, f, g, h, i] = normal.solve().ok_or(CalibrationError::Singular)?;
+        ([[a, d, e], [d, b, f], [e, f, c]], [g, h, i])
+    } else {
+        let mut normal = NormalEquations::<6>::new();
+        for p in points {
+            let [x, y, z] = p.map(f64::from);
+            normal.push(&[x * x, y * y, z * z, 2.0 * x, 2.0 * y, 2.0 * z], 1.0);
+        }
+        let [a, b, c, g, h, i] = normal.solve().ok_or(CalibrationError::Singular)?;
+        ([[a, 0.0, 0.0], [0.0, b, 0.0], [0.0, 0.0, c]], [g, h, i])
+    };
+
+    // Centre o = -Q⁻¹u, then (x - o)^T Q (x - o) = 1 + o^T Q o
+    let o = solve(q, u.map(|v| -v)).ok_or(CalibrationError::Singular)?;
+    let qo = q.map(|row| row[0] * o[0] + row[1] * o[1] + row[2] * o[2]);
+    let k = 1.0 + o[0] * qo[0] + o[1] * qo[1] + o[2] * qo[2];
+    if k <= 0.0 {
+        return Err(CalibrationError::NotEllipsoid);
+    }
+
+    // Symmetric square root of Q / k, so the correction does not rotate
+    let (values, v) = symmetric_eigen(q.map(|row| row.map(|x| x / k)));
+    if values.iter().any(|&l| l <= 0.0) {
+        return Err(CalibrationError::NotEllipsoid);
+    }
+    let roots = values.map(libm::sqrt);
+    let mut matrix = [[0.0f32; 3]; 3];
+    for (r, row) in matrix.iter_mut().enumerate() {
+        for (c, m) in row.iter_mut().enumerate() {
+            *m = (0..3).map(|k| v[r][k] * roots[k] * v[c][k]).sum::<f64>() as f32;
+        }
+    }
+
+    let calibration = AccelCalibration {
+        offset: o.map(|x| x as f32),
+        matrix,
+    };
+    Ok((calibration, misalignment))
+}
+
+/// Highest supported degree of the temperature polynomials
+pub const MAX_TEMP_DEGREE: usize = 3;
+
+/// Polynomial temperature model of bias and scale
+///
+/// With `dt = T - t_ref` the bias of an axis is `Σ bias[axis][k] * dt^k` and
+/// its relative scale error `Σ scale[axis][k] * dt^k`. A sample is corrected
+/// to `(v - bias(T)) / (1 + scale(T))`. Units follow the sensor: g for the
+/// accelerometer, °/s for the gyroscope.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct TempModel {
+    /// Reference temperature in °C
+    pub t_ref: f32,
+    pub bias: [[f32; MAX_TEMP_DEGREE + 1]; 3],
+    pub scale: [[f32; MAX_TEMP_DEGREE + 1]; 3],
+}
+
+impl TempModel {
+    /// Model without any correction
+    pub const fn identity(t_ref: f32) -> Self {
+        Self {
+            t_ref,
+            bias: [[0.0; MAX_TEMP_DEGREE + 1]; 3],
+            scale: [[0.0; MAX_TEMP_DEGREE + 1]; 3],
+        }
+    }
+
+    /// Correct a chip-frame sample taken at `temperature` °C
+    pub fn apply(&self, v: [f32; 3], temperature: f32) -> [f32; 3] {
+        let dt = temperature - self.t_ref;
+        let poly = |c: &[f32]| c.iter().rev().fold(0.0, |acc, &c| acc * dt + c);
+        [0, 1, 2].map(|i| (v[i] - poly(&self.bias[i])) / (1.0 + poly(&self.scale[i])))
+    }
+}
+
+/// Least-squares fitter for a [`TempModel`]
+///
+/// Feed it samples logged over the temperature range together with the
+/// value the sensor should have read. The scale polynomial of an axis is
+/// only fitted if its expected values span more than 0.1 units (e.g. the
+/// axis pointing up and down); otherwise only the bias is fitted, as for a
+/// gyroscope logged at rest.
+#[derive(Debug, Clone)]
+pub struct TempFitter {
+    t_ref: f32,
+    degree: usize,
+    axes: [NormalEquations<{ 2 * (MAX_TEMP_DEGREE + 1) }>; 3],
+    expected_min: [f32; 3],
+    expected_max: [f32; 3],
+    samples: u32,
+}
+
+impl TempFitter {
+    /// Fitter for polynomials of `degree` (at most [`MAX_TEMP_DEGREE`])
+    /// around `t_ref` °C
+    pub fn new(t_ref: f32, degree: usize) -> Self {
+        Self {
+            t_ref,
+            degree: degree.min(MAX_TEMP_DEGREE),
+            axes: [NormalEquations::new(); 3],
+            expected_min: [f32::INFINITY; 3],
+            expected_max: [f32::NEG_INFINITY; 3],
+            samples: 0,
+        }
+    }
+
+    /// Add a sample `measured` at `temperature` °C that should have read
+    /// `expected`
+    pub fn add(&mut self, temperature: f32, measured: [f32; 3], expected: [f32; 3]) {
+        let dt = f64::from(temperature - self.t_ref);
+        for axis in 0..3 {
+            let e = f64::from(expected[axis]);
+            let mut row = [0.0; 2 * (MAX_TEMP_DEGREE + 1)];
+            let mut power = 1.0;
+            for k in 0..=MAX_TEMP_DEGREE {
+                row[k] = power;
+                row[MAX_TEMP_DEGREE + 1 + k] = e * power;
+                power *= dt;
+            }
+            // measured - expected = bias(T) + expected * scale(T)
+            self.axes[axis].push(&row, f64::from(measured[axis]) - e);
+            self.expected_min[axis] = self.expected_min[axis].min(expected[axis]);
+            self.expected_max[axis] = self.expected_max[axis].max(expected[axis]);
+        }
+        self.samples += 1;
+    }
+
+    /// Add a sample taken at rest, e.g. a gyroscope reading
+    pub fn add_at_rest(&mut self, temperature: f32, measured: [f32; 3]) {
+        self.add(temperature, measured, [0.0; 3]);
+    }
+
+    pub fn fit(&self) -> Result<TempModel, CalibrationError> {
+        if self.samples < 2 * (self.degree as u32 + 1) {
+            return Err(CalibrationError::NotEnoughSamples);
+        }
+        let mut model = TempModel::identity(self.t_ref);
+        for axis in 0..3 {
+            let fit_scale = self.expected_max[axis] - self.expected_min[axis] > 0.1;
+            let mut active = [false; 2 * (MAX_TEMP_DEGREE + 1)];
+            for k in 0..=self.degree {
+                active[k] = true;
+                active[MAX_TEMP_DEGREE + 1 + k] = fit_scale;
+            }
+            let x = self.axes[axis]
+                .solve_active(&active)
+                .ok_or(CalibrationError::Singular)?;
+            for k in 0..=MAX_TEMP_DEGREE {
+                model.bias[axis][k] = x[k] as f32;
+                model.scale[axis][k] = x[MAX_TEMP_DEGREE + 1 + k] as f32;
+            }
+        }
+        Ok(model)
+    }
+}
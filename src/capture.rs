@@ -0,0 +1,220 @@
+//! Pre-trigger recording of full-rate samples around an event
+//!
+//! A [`PreTrigger`] buffer records every accelerometer and gyroscope sample.
+//! On a trigger, from software, an [`EventDetector`](crate::events::EventDetector)
+//! event or the accelerometer interrupt, it keeps the samples from before
+//! the trigger, records the configured number after it and freezes until
+//! the [`Capture`] was read out.
+
+use crate::sample::{AccelSample, GyroSample};
+
+/// Accelerometer and gyroscope sample read together
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct CaptureSample {
+    pub accel: AccelSample,
+    pub gyro: GyroSample,
+}
+
+/// Window frozen around a trigger
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct CaptureConfig {
+    /// Samples kept from before the trigger
+    pub pre: usize,
+    /// Samples recorded from the trigger on, including the one that
+    /// triggered
+    pub post: usize,
+    /// Trigger when the acceleration magnitude exceeds this in g
+    pub threshold_g: Option<f32>,
+}
+
+/// Why a [`PreTrigger`] could not be configured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum CaptureError {
+    /// `pre + post` exceeds the capacity or `post` is 0
+    InvalidWindow,
+}
+
+/// State of a [`PreTrigger`] buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum CaptureState {
+    /// Recording continuously, waiting for a trigger
+    Armed,
+    /// Recording the samples after the trigger
+    Triggered { remaining: usize },
+    /// Capture complete, new samples are dropped until
+    /// [`PreTrigger::rearm`]
+    Frozen,
+}
+
+/// Ring buffer of the last `N` samples that freezes around a trigger
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct PreTrigger<const N: usize> {
+    config: CaptureConfig,
+    samples: [CaptureSample; N],
+    /// Slot of the next sample
+    next: usize,
+    len: usize,
+    state: CaptureState,
+    /// Samples before the trigger in the capture
+    pre: usize,
+}
+
+impl<const N: usize> PreTrigger<N> {
+    pub fn new(config: CaptureConfig) -> Result<Self, CaptureError> {
+        Self::check(&config)?;
+        Ok(PreTrigger {
+            config,
+            samples: [CaptureSample::default(); N],
+            next: 0,
+            len: 0,
+            state: CaptureState::Armed,
+            pre: 0,
+        })
+    }
+
+    pub fn config(&self) -> &CaptureConfig {
+        &self.config
+    }
+
+    /// Change the window, discarding recorded samples and any capture
+    pub fn set_config(&mut self, config: CaptureConfig) -> Result<(), CaptureError> {
+        Self::check(&config)?;
+        self.config = config;
+        self.rearm();
+        Ok(())
+    }
+
+    pub fn state(&self) -> CaptureState {
+        self.state
+    }
+
+    /// Record a sample, returns whether the capture is complete
+    pub fn push(&mut self, accel: &AccelSample, gyro: &GyroSample) -> bool {
+        if self.state == CaptureState::Armed {
+            if let Some(threshold) = self.config.threshold_g {
+                if crate::math::norm3(accel.xyz) > threshold {
+                    self.trigger();
+                }
+            }
+        }
+        match self.state {
+            CaptureState::Armed => {}
+            CaptureState::Triggered { remaining } => {
+                self.state = if remaining > 1 {
+                    CaptureState::Triggered {
+                        remaining: remaining - 1,
+                    }
+                } else {
+                    CaptureState::Frozen
+                };
+            }
+            CaptureState::Frozen => return true,
+        }
+        self.samples[self.next] = CaptureSample {
+            accel: *accel,
+            gyro: *gyro,
+        };
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        self.state == CaptureState::Frozen
+    }
+
+    /// Start the post-trigger window with the next sample
+    ///
+    /// Call from the accelerometer interrupt handler or on an event.
+    /// Ignored unless armed. Fewer samples than configured precede the
+    /// trigger if it comes early.
+    pub fn trigger(&mut self) {
+        if self.state == CaptureState::Armed {
+            self.pre = self.len.min(self.config.pre);
+            self.state = CaptureState::Triggered {
+                remaining: self.config.post,
+            };
+        }
+    }
+
+    /// The frozen samples, `None` until the capture is complete
+    pub fn capture(&self) -> Option<Capture<'_>> {
+        if self.state != CaptureState::Frozen {
+            return None;
+        }
+        let len = self.pre + self.config.post;
+        // `next` follows the last sample of the capture
+        let start = (self.next + N - len) % N;
+        let (first, second) = if start + len <= N {
+            (&self.samples[start..start + len], &[][..])
+        } else {
+            (&self.samples[start..], &self.samples[..self.next])
+        };
+        Some(Capture {
+            first,
+            second,
+            trigger_index: self.pre,
+        })
+    }
+
+    /// Discard recorded samples and any capture and record again
+    pub fn rearm(&mut self) {
+        self.next = 0;
+        self.len = 0;
+        self.pre = 0;
+        self.state = CaptureState::Armed;
+    }
+
+    fn check(config: &CaptureConfig) -> Result<(), CaptureError> {
+        if config.post == 0 || config.pre.saturating_add(config.post) > N {
+            return Err(CaptureError::InvalidWindow);
+        }
+        Ok(())
+    }
+}
+
+/// Samples of a frozen [`PreTrigger`] in the order they were recorded
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Capture<'a> {
+    first: &'a [CaptureSample],
+    second: &'a [CaptureSample],
+    trigger_index: usize,
+}
+
+impl<'a> Capture<'a> {
+    pub fn len(&self) -> usize {
+        self.first.len() + self.second.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Index of the first sample from the trigger on
+    pub fn trigger_index(&self) -> usize {
+        self.trigger_index
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a CaptureSample> {
+        match index.checked_sub(self.first.len()) {
+            None => self.first.get(index),
+            Some(index) => self.second.get(index),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &'a CaptureSample> {
+        self.first.iter().chain(self.second)
+    }
+
+    /// Samples before the trigger
+    pub fn pre_trigger(&self) -> impl Iterator<Item = &'a CaptureSample> {
+        self.iter().take(self.trigger_index)
+    }
+
+    /// Samples from the trigger on
+    pub fn post_trigger(&self) -> impl Iterator<Item = &'a CaptureSample> {
+        self.iter().skip(self.trigger_index)
+    }
+}
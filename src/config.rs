@@ -0,0 +1,299 @@
+//! Register configuration snapshots
+//!
+//! Both sensors revert to their reset values after a brown-out or a soft
+//! reset. A snapshot holds the raw configuration registers so that it can be
+//! stored (e.g. in flash), compared and re-applied in one call.
+
+use core::fmt;
+
+use embedded_hal_async::delay::DelayNs;
+
+use crate::{
+    acc_impl::{Accelerometer, AccelerometerRange},
+    gyro_impl::{GyroBandwidth, Gyroscope, GyroscopeRange},
+    interface::{AsyncReadData, AsyncWriteData},
+    register_address::{AccRegisters, GyroRegisters},
+    Error, Sensor,
+};
+
+/// Outcome of comparing the chip's configuration with the driver's shadow
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum ShadowCheck {
+    /// Chip and shadow agree
+    Consistent,
+    /// The chip lost its configuration, most likely through a reset
+    ResetDetected,
+    /// The chip lost its configuration and the shadow was written again
+    Restored,
+}
+
+/// Accelerometer configuration registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AccelConfig {
+    /// `ACC_CONF` (0x40): bandwidth and output data rate
+    pub conf: u8,
+    /// `ACC_RANGE` (0x41)
+    pub range: u8,
+    /// `ACC_PWR_CONF` (0x7C): 0x03 suspend, 0x00 active
+    pub pwr_conf: u8,
+    /// `ACC_PWR_CTRL` (0x7D): 0x00 off, 0x04 on
+    pub pwr_ctrl: u8,
+}
+
+impl AccelConfig {
+    /// Length of the serialised snapshot
+    pub const LEN: usize = 4;
+
+    /// Serialise into a compact byte array
+    pub const fn to_bytes(&self) -> [u8; Self::LEN] {
+        [self.conf, self.range, self.pwr_conf, self.pwr_ctrl]
+    }
+
+    /// Deserialise a snapshot, rejecting values the chip does not accept
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let config = Self {
+            conf: bytes[0],
+            range: bytes[1],
+            pwr_conf: bytes[2],
+            pwr_ctrl: bytes[3],
+        };
+        config.is_valid().then_some(config)
+    }
+
+    /// Decoded measurement range
+    pub fn accel_range(&self) -> Option<AccelerometerRange> {
+        AccelerometerRange::from_register(self.range)
+    }
+
+    /// Output data rate in Hz
+    pub fn odr_hz(&self) -> Option<f32> {
+        match self.conf & 0x0F {
+            odr @ 0x05..=0x0C => Some(12.5 * (1 << (odr - 0x05)) as f32),
+            _ => None,
+        }
+    }
+
+    /// -3 dB bandwidth in Hz of the configured ODR and oversampling
+    pub fn bandwidth_hz(&self) -> Option<f32> {
+        // Normal, OSR2 and OSR4 for 12.5 Hz up to 1600 Hz ODR
+        const BANDWIDTH: [[f32; 8]; 3] = [
+            [5.0, 10.0, 20.0, 40.0, 80.0, 145.0, 230.0, 280.0],
+            [2.0, 5.0, 9.0, 19.0, 38.0, 75.0, 140.0, 234.0],
+            [1.0, 3.0, 5.0, 10.0, 20.0, 40.0, 80.0, 145.0],
+        ];
+        let odr = match self.conf & 0x0F {
+            odr @ 0x05..=0x0C => (odr - 0x05) as usize,
+            _ => return None,
+        };
+        let bwp = match self.conf >> 4 {
+            0x0A => 0,
+            0x09 => 1,
+            0x08 => 2,
+            _ => return None,
+        };
+        Some(BANDWIDTH[bwp][odr])
+    }
+
+    /// Decoded range of a valid configuration, or the first register and
+    /// value the chip does not accept
+    pub(crate) fn validate(&self) -> Result<AccelerometerRange, (AccRegisters, u8)> {
+        let range = self
+            .accel_range()
+            .ok_or((AccRegisters::RANGE, self.range))?;
+        if !matches!(self.pwr_conf, 0x00 | 0x03) {
+            return Err((AccRegisters::PWR_CONF, self.pwr_conf));
+        }
+        if !matches!(self.pwr_ctrl, 0x00 | 0x04) {
+            return Err((AccRegisters::PWR_CTRL, self.pwr_ctrl));
+        }
+        Ok(range)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+}
+
+impl Default for AccelConfig {
+    /// Register values after power-on reset
+    fn default() -> Self {
+        Self {
+            conf: 0xA8,
+            range: AccelerometerRange::Scale6g as u8,
+            pwr_conf: 0x03,
+            pwr_ctrl: 0x00,
+        }
+    }
+}
+
+/// Gyroscope configuration registers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct GyroConfig {
+    /// `GYRO_RANGE` (0x0F)
+    pub range: u8,
+    /// `GYRO_BANDWIDTH` (0x10), bit 7 always reads as 1
+    pub bandwidth: u8,
+    /// `GYRO_LPM1` (0x11): power mode
+    pub lpm1: u8,
+}
+
+impl GyroConfig {
+    /// Length of the serialised snapshot
+    pub const LEN: usize = 3;
+
+    /// Serialise into a compact byte array
+    pub const fn to_bytes(&self) -> [u8; Self::LEN] {
+        [self.range, self.bandwidth, self.lpm1]
+    }
+
+    /// Deserialise a snapshot, rejecting values the chip does not accept
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let config = Self {
+            range: bytes[0],
+            bandwidth: bytes[1] | 0x80,
+            lpm1: bytes[2],
+        };
+        config.is_valid().then_some(config)
+    }
+
+    /// Decoded measurement range
+    pub fn gyro_range(&self) -> Option<GyroscopeRange> {
+        GyroscopeRange::from_register(self.range)
+    }
+
+    /// Decoded output data rate and filter bandwidth
+    pub fn gyro_bandwidth(&self) -> Option<GyroBandwidth> {
+        GyroBandwidth::from_register(self.bandwidth)
+    }
+
+    /// Decoded range of a valid configuration, or the first register and
+    /// value the chip does not accept
+    pub(crate) fn validate(&self) -> Result<GyroscopeRange, (GyroRegisters, u8)> {
+        let range = self
+            .gyro_range()
+            .ok_or((GyroRegisters::RANGE, self.range))?;
+        if self.bandwidth & 0x7F > 0x07 {
+            return Err((GyroRegisters::BANDWIDTH, self.bandwidth));
+        }
+        if !matches!(self.lpm1, 0x00 | 0x80 | 0x20) {
+            return Err((GyroRegisters::LPM1, self.lpm1));
+        }
+        Ok(range)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.validate().is_ok()
+    }
+}
+
+impl Default for GyroConfig {
+    /// Register values after power-on reset
+    fn default() -> Self {
+        Self {
+            range: GyroscopeRange::Scale2000 as u8,
+            bandwidth: 0x80,
+            lpm1: 0x00,
+        }
+    }
+}
+
+/// Failure of either sensor in a [`DeviceConfig`] operation
+///
+/// The sensors may sit on different buses, each with its own error type.
+#[derive(Debug)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum DeviceError<AE, GE> {
+    Accelerometer(Error<AE>),
+    Gyroscope(Error<GE>),
+}
+
+impl<AE: fmt::Debug, GE: fmt::Debug> fmt::Display for DeviceError<AE, GE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::Accelerometer(e) => e.fmt(f),
+            DeviceError::Gyroscope(e) => e.fmt(f),
+        }
+    }
+}
+
+impl<AE: fmt::Debug, GE: fmt::Debug> core::error::Error for DeviceError<AE, GE> {}
+
+/// Configuration of both sensors
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct DeviceConfig {
+    pub acc: AccelConfig,
+    pub gyro: GyroConfig,
+}
+
+impl DeviceConfig {
+    /// Length of the serialised snapshot
+    pub const LEN: usize = AccelConfig::LEN + GyroConfig::LEN;
+
+    /// Serialise into a compact byte array, accelerometer first
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut bytes = [0; Self::LEN];
+        bytes[..AccelConfig::LEN].copy_from_slice(&self.acc.to_bytes());
+        bytes[AccelConfig::LEN..].copy_from_slice(&self.gyro.to_bytes());
+        bytes
+    }
+
+    /// Deserialise a snapshot, rejecting values the chip does not accept
+    pub fn from_bytes(bytes: &[u8; Self::LEN]) -> Option<Self> {
+        let (acc, gyro) = bytes.split_at(AccelConfig::LEN);
+        Some(Self {
+            acc: AccelConfig::from_bytes(acc.try_into().ok()?)?,
+            gyro: GyroConfig::from_bytes(gyro.try_into().ok()?)?,
+        })
+    }
+
+    /// Read the current configuration of both sensors
+    pub async fn read<AI, GI, AE, GE>(
+        acc: &mut Accelerometer<AI>,
+        gyro: &mut Gyroscope<GI>,
+    ) -> Result<Self, DeviceError<AE, GE>>
+    where
+        AI: AsyncReadData<Error = Error<AE>> + AsyncWriteData<Error = Error<AE>>,
+        GI: AsyncReadData<Error = Error<GE>> + AsyncWriteData<Error = Error<GE>>,
+    {
+        Ok(Self {
+            acc: acc
+                .read_config()
+                .await
+                .map_err(DeviceError::Accelerometer)?,
+            gyro: gyro.read_config().await.map_err(DeviceError::Gyroscope)?,
+        })
+    }
+
+    /// Write the configuration to both sensors
+    ///
+    /// Both snapshots are checked first, an invalid value leaves either
+    /// sensor untouched.
+    pub async fn apply<AI, GI, AE, GE>(
+        &self,
+        acc: &mut Accelerometer<AI>,
+        gyro: &mut Gyroscope<GI>,
+        delay: &mut impl DelayNs,
+    ) -> Result<(), DeviceError<AE, GE>>
+    where
+        AI: AsyncReadData<Error = Error<AE>> + AsyncWriteData<Error = Error<AE>>,
+        GI: AsyncReadData<Error = Error<GE>> + AsyncWriteData<Error = Error<GE>>,
+    {
+        if let Err((reg, value)) = self.gyro.validate() {
+            return Err(DeviceError::Gyroscope(Error::InvalidConfig {
+                sensor: Sensor::Gyroscope,
+                reg: reg as u8,
+                value,
+            }));
+        }
+        acc.apply_config(&self.acc, delay)
+            .await
+            .map_err(DeviceError::Accelerometer)?;
+        gyro.apply_config(&self.gyro)
+            .await
+            .map_err(DeviceError::Gyroscope)
+    }
+}
@@ -0,0 +1,394 @@
+//! Tap, free-fall, impact and stillness detection in software
+//!
+//! An [`EventDetector`] runs on every accelerometer sample, optionally with
+//! the gyroscope sample read alongside it. Times come from the sensor time
+//! of the samples, in µs since the first sample.
+
+use crate::math::norm3;
+use crate::sample::{AccelSample, GyroSample, SENSOR_TIME_TICK_US};
+
+/// Time constant of the gravity and orientation baseline taps stand out from
+const BASELINE_TAU_S: f32 = 0.2;
+
+/// Detected event
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum EventKind {
+    /// Reported once the double tap window passed without a second tap
+    SingleTap,
+    DoubleTap,
+    /// Magnitude stayed below the free-fall threshold for the set duration
+    FreeFall,
+    /// Magnitude exceeded the impact threshold, reported once it dropped
+    Impact {
+        peak_g: f32,
+    },
+    /// Reported after the set duration without motion
+    Still,
+    /// Motion after [`EventKind::Still`]
+    Moving,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Event {
+    pub kind: EventKind,
+    /// When the event began in µs since the first sample: the start of the
+    /// tap, fall or stillness, the peak of an impact
+    pub time_us: u64,
+}
+
+/// Short shock against the acceleration baseline
+///
+/// Shocks reaching the impact threshold are reported as impacts only.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct TapConfig {
+    /// Deviation from the baseline in g that starts a tap
+    pub threshold_g: f32,
+    /// The tap ends once the deviation drops below `threshold_g - hysteresis_g`
+    pub hysteresis_g: f32,
+    /// Longer shocks are motion, not taps
+    pub max_duration_us: u32,
+    /// Ringing ignored after a tap
+    pub quiet_us: u32,
+    /// Time from the first tap within which a second one makes a double
+    /// tap, 0 reports every tap at once as a single tap
+    pub double_window_us: u32,
+}
+
+impl Default for TapConfig {
+    fn default() -> Self {
+        TapConfig {
+            threshold_g: 1.0,
+            hysteresis_g: 0.3,
+            max_duration_us: 60_000,
+            quiet_us: 60_000,
+            double_window_us: 400_000,
+        }
+    }
+}
+
+/// Magnitude near zero
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct FreeFallConfig {
+    pub threshold_g: f32,
+    /// The fall ends once the magnitude exceeds `threshold_g + hysteresis_g`
+    pub hysteresis_g: f32,
+    /// 100 ms is a drop of about 5 cm
+    pub duration_us: u32,
+}
+
+impl Default for FreeFallConfig {
+    fn default() -> Self {
+        FreeFallConfig {
+            threshold_g: 0.35,
+            hysteresis_g: 0.1,
+            duration_us: 100_000,
+        }
+    }
+}
+
+/// Magnitude above a shock level
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ImpactConfig {
+    /// Keep below the accelerometer range, the peak saturates there
+    pub threshold_g: f32,
+    /// The impact ends once the magnitude drops below
+    /// `threshold_g - hysteresis_g`
+    pub hysteresis_g: f32,
+}
+
+impl Default for ImpactConfig {
+    fn default() -> Self {
+        ImpactConfig {
+            threshold_g: 4.0,
+            hysteresis_g: 0.5,
+        }
+    }
+}
+
+/// No rotation and no acceleration against the baseline
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct StillnessConfig {
+    /// Largest deviation from the acceleration baseline in g
+    pub accel_g: f32,
+    /// Largest rate in °/s, ignored without gyroscope samples
+    pub gyro_dps: f32,
+    /// Factor on both limits before motion ends stillness
+    pub hysteresis: f32,
+    pub duration_us: u32,
+}
+
+impl Default for StillnessConfig {
+    fn default() -> Self {
+        StillnessConfig {
+            accel_g: 0.02,
+            gyro_dps: 1.0,
+            hysteresis: 2.0,
+            duration_us: 1_000_000,
+        }
+    }
+}
+
+/// Detectors to run, `None` disables one
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct EventConfig {
+    pub tap: Option<TapConfig>,
+    pub free_fall: Option<FreeFallConfig>,
+    pub impact: Option<ImpactConfig>,
+    pub stillness: Option<StillnessConfig>,
+}
+
+impl Default for EventConfig {
+    fn default() -> Self {
+        EventConfig {
+            tap: Some(TapConfig::default()),
+            free_fall: Some(FreeFallConfig::default()),
+            impact: Some(ImpactConfig::default()),
+            stillness: Some(StillnessConfig::default()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+enum TapState {
+    #[default]
+    Idle,
+    /// `tap` is cleared once the shock turns out to be an impact
+    Shock {
+        start: u64,
+        tap: bool,
+    },
+    Quiet {
+        until: u64,
+    },
+}
+
+/// Software event engine over accelerometer and gyroscope samples
+///
+/// [`Self::update`] returns at most one event per detector and sample. The
+/// thresholds are in g and °/s as delivered by
+/// [`AccelSample::xyz`] and [`GyroSample::xyz`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct EventDetector {
+    config: EventConfig,
+    last: Option<AccelSample>,
+    /// Sensor time ticks since the first sample
+    ticks: u64,
+    baseline: [f32; 3],
+    tap: TapState,
+    pending_tap: Option<u64>,
+    fall_start: Option<u64>,
+    fall_reported: bool,
+    impact_peak: Option<(f32, u64)>,
+    still_since: Option<u64>,
+    still: bool,
+}
+
+impl EventDetector {
+    pub fn new(config: EventConfig) -> Self {
+        EventDetector {
+            config,
+            last: None,
+            ticks: 0,
+            baseline: [0.0; 3],
+            tap: TapState::Idle,
+            pending_tap: None,
+            fall_start: None,
+            fall_reported: false,
+            impact_peak: None,
+            still_since: None,
+            still: false,
+        }
+    }
+
+    pub fn config(&self) -> &EventConfig {
+        &self.config
+    }
+
+    /// Change the thresholds, restarting all detectors
+    pub fn set_config(&mut self, config: EventConfig) {
+        *self = Self::new(config);
+    }
+
+    /// Whether the sensor is currently still
+    pub fn is_still(&self) -> bool {
+        self.still
+    }
+
+    /// Forget the baseline and any event in progress
+    pub fn reset(&mut self) {
+        *self = Self::new(self.config);
+    }
+
+    /// Process one sample, events in the order tap, free fall, impact,
+    /// stillness
+    pub fn update(&mut self, accel: &AccelSample, gyro: Option<&GyroSample>) -> [Option<Event>; 4] {
+        let Some(last) = self.last.replace(*accel) else {
+            self.baseline = accel.xyz;
+            return [None; 4];
+        };
+        let dt = accel.seconds_since(&last);
+        self.ticks += u64::from(accel.ticks_since(&last));
+        let t = (self.ticks as f64 * f64::from(SENSOR_TIME_TICK_US)) as u64;
+
+        let magnitude = norm3(accel.xyz);
+        let deviation = norm3(core::array::from_fn(|i| accel.xyz[i] - self.baseline[i]));
+        let events = [
+            self.config.tap.and_then(|c| self.tap(&c, deviation, t)),
+            self.config
+                .free_fall
+                .and_then(|c| self.free_fall(&c, magnitude, t)),
+            self.config
+                .impact
+                .and_then(|c| self.impact(&c, magnitude, t)),
+            self.config
+                .stillness
+                .and_then(|c| self.stillness(&c, deviation, gyro, t)),
+        ];
+
+        // Follow gravity and slow orientation changes, not the shocks
+        if !matches!(self.tap, TapState::Shock { .. }) {
+            let alpha = dt / (BASELINE_TAU_S + dt);
+            for (b, x) in self.baseline.iter_mut().zip(accel.xyz) {
+                *b += alpha * (x - *b);
+            }
+        }
+        events
+    }
+
+    fn tap(&mut self, c: &TapConfig, deviation: f32, t: u64) -> Option<Event> {
+        match self.tap {
+            TapState::Idle if deviation > c.threshold_g => {
+                self.tap = TapState::Shock {
+                    start: t,
+                    tap: self.impact_peak.is_none(),
+                };
+            }
+            TapState::Shock { start, .. } if self.impact_peak.is_some() => {
+                self.tap = TapState::Shock { start, tap: false };
+            }
+            TapState::Shock { start, tap } if deviation < c.threshold_g - c.hysteresis_g => {
+                if !tap || t - start > u64::from(c.max_duration_us) {
+                    // Motion or impact, also cancels a pending first tap
+                    self.tap = TapState::Idle;
+                    self.pending_tap = None;
+                    return None;
+                }
+                self.tap = TapState::Quiet {
+                    until: t + u64::from(c.quiet_us),
+                };
+                if c.double_window_us == 0 {
+                    return Some(Event {
+                        kind: EventKind::SingleTap,
+                        time_us: start,
+                    });
+                }
+                if let Some(first) = self.pending_tap.take() {
+                    if start - first <= u64::from(c.double_window_us) {
+                        return Some(Event {
+                            kind: EventKind::DoubleTap,
+                            time_us: first,
+                        });
+                    }
+                    // Too late for a double tap, report the first and wait
+                    // for a partner of this one
+                    self.pending_tap = Some(start);
+                    return Some(Event {
+                        kind: EventKind::SingleTap,
+                        time_us: first,
+                    });
+                }
+                self.pending_tap = Some(start);
+            }
+            TapState::Quiet { until } if t >= until => {
+                self.tap = TapState::Idle;
+            }
+            _ => {}
+        }
+        match (self.pending_tap, self.tap) {
+            (Some(first), TapState::Idle | TapState::Quiet { .. })
+                if t - first > u64::from(c.double_window_us) =>
+            {
+                self.pending_tap = None;
+                Some(Event {
+                    kind: EventKind::SingleTap,
+                    time_us: first,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    fn free_fall(&mut self, c: &FreeFallConfig, magnitude: f32, t: u64) -> Option<Event> {
+        if magnitude < c.threshold_g {
+            let start = *self.fall_start.get_or_insert(t);
+            if !self.fall_reported && t - start >= u64::from(c.duration_us) {
+                self.fall_reported = true;
+                return Some(Event {
+                    kind: EventKind::FreeFall,
+                    time_us: start,
+                });
+            }
+        } else if magnitude > c.threshold_g + c.hysteresis_g {
+            self.fall_start = None;
+            self.fall_reported = false;
+        }
+        None
+    }
+
+    fn impact(&mut self, c: &ImpactConfig, magnitude: f32, t: u64) -> Option<Event> {
+        if magnitude > c.threshold_g {
+            if self.impact_peak.is_none_or(|(peak, _)| magnitude > peak) {
+                self.impact_peak = Some((magnitude, t));
+            }
+        } else if magnitude < c.threshold_g - c.hysteresis_g {
+            if let Some((peak_g, time_us)) = self.impact_peak.take() {
+                return Some(Event {
+                    kind: EventKind::Impact { peak_g },
+                    time_us,
+                });
+            }
+        }
+        None
+    }
+
+    fn stillness(
+        &mut self,
+        c: &StillnessConfig,
+        deviation: f32,
+        gyro: Option<&GyroSample>,
+        t: u64,
+    ) -> Option<Event> {
+        let rate = gyro.map_or(0.0, |g| norm3(g.xyz));
+        if self.still {
+            if deviation > c.accel_g * c.hysteresis || rate > c.gyro_dps * c.hysteresis {
+                self.still = false;
+                self.still_since = None;
+                return Some(Event {
+                    kind: EventKind::Moving,
+                    time_us: t,
+                });
+            }
+        } else if deviation <= c.accel_g && rate <= c.gyro_dps {
+            let since = *self.still_since.get_or_insert(t);
+            if t - since >= u64::from(c.duration_us) {
+                self.still = true;
+                return Some(Event {
+                    kind: EventKind::Still,
+                    time_us: since,
+                });
+            }
+        } else {
+            self.still_since = None;
+        }
+        None
+    }
+}
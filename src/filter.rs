@@ -0,0 +1,328 @@
+//! Digital filters for three-axis samples
+//!
+//! A [`FilterChain`] runs cascaded [`Biquad`] stages over every sample and a
+//! [`DynamicNotch`] follows vibration peaks. Both implement [`SampleFilter`]
+//! for [`Gyroscope::filtered`](crate::gyro_impl::Gyroscope::filtered) and
+//! [`Accelerometer::filtered`](crate::acc_impl::Accelerometer::filtered).
+//! Filters are configured in Hz and their coefficients are recomputed
+//! whenever the sample rate changes.
+
+use core::f32::consts::{FRAC_1_SQRT_2, PI};
+
+mod dynamic;
+
+pub use dynamic::{DynamicNotch, DynamicNotchConfig};
+
+/// Per-sample three-axis filter whose response depends on the sample rate
+pub trait SampleFilter {
+    /// Called before every sample, cheap if the rate did not change
+    fn set_sample_rate(&mut self, sample_rate_hz: f32);
+
+    fn process(&mut self, x: [f32; 3]) -> [f32; 3];
+}
+
+/// Two filters applied one after the other
+impl<A: SampleFilter, B: SampleFilter> SampleFilter for (A, B) {
+    fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        self.0.set_sample_rate(sample_rate_hz);
+        self.1.set_sample_rate(sample_rate_hz);
+    }
+
+    fn process(&mut self, x: [f32; 3]) -> [f32; 3] {
+        let x = self.0.process(x);
+        self.1.process(x)
+    }
+}
+
+/// Response of a [`Biquad`] stage
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum FilterKind {
+    /// Second-order low-pass, `q` of 1/√2 is a Butterworth response
+    LowPass { cutoff_hz: f32, q: f32 },
+    /// Rejects `centre_hz`, the -3 dB width is `centre_hz / q`
+    Notch { centre_hz: f32, q: f32 },
+    /// Rejects `low_hz..high_hz`, a notch with the given -3 dB edges
+    BandStop { low_hz: f32, high_hz: f32 },
+}
+
+impl FilterKind {
+    /// Butterworth low-pass at `cutoff_hz`
+    pub const fn low_pass(cutoff_hz: f32) -> Self {
+        FilterKind::LowPass {
+            cutoff_hz,
+            q: FRAC_1_SQRT_2,
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        let positive = |x: f32| x.is_finite() && x > 0.0;
+        match *self {
+            FilterKind::LowPass { cutoff_hz, q } => positive(cutoff_hz) && positive(q),
+            FilterKind::Notch { centre_hz, q } => positive(centre_hz) && positive(q),
+            FilterKind::BandStop { low_hz, high_hz } => {
+                positive(low_hz) && positive(high_hz) && low_hz < high_hz
+            }
+        }
+    }
+}
+
+/// Why a filter stage could not be added
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum FilterError {
+    /// No room for another stage
+    Full,
+    /// No stage at this index
+    NoStage,
+    /// Frequencies or Q not positive, or band edges in the wrong order
+    InvalidParameters,
+}
+
+/// Normalised coefficients, `a0` is 1
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+struct Coefficients {
+    b: [f32; 3],
+    a: [f32; 2],
+}
+
+impl Coefficients {
+    /// Bilinear-transform designs after the RBJ audio EQ cookbook, `None`
+    /// if the frequency is not below Nyquist
+    fn design(kind: &FilterKind, sample_rate_hz: f32) -> Option<Self> {
+        let (frequency, q) = match *kind {
+            FilterKind::LowPass { cutoff_hz, q } => (cutoff_hz, q),
+            FilterKind::Notch { centre_hz, q } => (centre_hz, q),
+            FilterKind::BandStop { low_hz, high_hz } => {
+                let centre = libm::sqrtf(low_hz * high_hz);
+                (centre, centre / (high_hz - low_hz))
+            }
+        };
+        if sample_rate_hz.is_nan() || sample_rate_hz <= 0.0 || frequency >= 0.5 * sample_rate_hz {
+            return None;
+        }
+        let (sin, cos) = libm::sincosf(2.0 * PI * frequency / sample_rate_hz);
+        let alpha = sin / (2.0 * q);
+        let b = match kind {
+            FilterKind::LowPass { .. } => [0.5 * (1.0 - cos), 1.0 - cos, 0.5 * (1.0 - cos)],
+            FilterKind::Notch { .. } | FilterKind::BandStop { .. } => [1.0, -2.0 * cos, 1.0],
+        };
+        let a0 = 1.0 + alpha;
+        Some(Coefficients {
+            b: b.map(|b| b / a0),
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+        })
+    }
+
+    fn dc_gain(&self) -> f32 {
+        (self.b[0] + self.b[1] + self.b[2]) / (1.0 + self.a[0] + self.a[1])
+    }
+}
+
+/// Second-order IIR section filtering each axis independently
+///
+/// Passes samples through unchanged until a sample rate is set, or while
+/// the frequency is not below the Nyquist frequency of that rate. The state
+/// is primed with the first sample, so a constant input such as gravity
+/// does not ring.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Biquad {
+    kind: FilterKind,
+    sample_rate_hz: Option<f32>,
+    coefficients: Option<Coefficients>,
+    /// Transposed direct form II state per axis
+    state: [[f32; 2]; 3],
+    primed: [bool; 3],
+}
+
+impl Biquad {
+    pub fn new(kind: FilterKind) -> Result<Self, FilterError> {
+        if !kind.is_valid() {
+            return Err(FilterError::InvalidParameters);
+        }
+        Ok(Biquad {
+            kind,
+            sample_rate_hz: None,
+            coefficients: None,
+            state: [[0.0; 2]; 3],
+            primed: [false; 3],
+        })
+    }
+
+    pub fn kind(&self) -> &FilterKind {
+        &self.kind
+    }
+
+    /// Change the response, keeping the sample rate
+    pub fn set_kind(&mut self, kind: FilterKind) -> Result<(), FilterError> {
+        if !kind.is_valid() {
+            return Err(FilterError::InvalidParameters);
+        }
+        self.kind = kind;
+        // Keep the state so a notch can track a changing frequency smoothly
+        let active = self.is_active();
+        self.coefficients = self.design();
+        if !active {
+            self.reset();
+        }
+        Ok(())
+    }
+
+    pub fn sample_rate_hz(&self) -> Option<f32> {
+        self.sample_rate_hz
+    }
+
+    /// Recompute the coefficients if the rate differs from the current one
+    pub fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        if self.sample_rate_hz != Some(sample_rate_hz) {
+            self.sample_rate_hz = Some(sample_rate_hz);
+            self.coefficients = self.design();
+            self.reset();
+        }
+    }
+
+    /// Whether the stage filters at the current sample rate
+    pub fn is_active(&self) -> bool {
+        self.coefficients.is_some()
+    }
+
+    /// Forget the filter state, the next sample primes it again
+    pub fn reset(&mut self) {
+        self.state = [[0.0; 2]; 3];
+        self.primed = [false; 3];
+    }
+
+    pub fn process(&mut self, x: [f32; 3]) -> [f32; 3] {
+        [0, 1, 2].map(|axis| self.process_axis(axis, x[axis]))
+    }
+
+    /// Filter one axis, e.g. when each axis needs its own frequency
+    pub(crate) fn process_axis(&mut self, axis: usize, x: f32) -> f32 {
+        let Some(c) = self.coefficients else {
+            return x;
+        };
+        let state = &mut self.state[axis];
+        if !self.primed[axis] {
+            // Steady state of a constant input
+            let y = c.dc_gain() * x;
+            state[1] = c.b[2] * x - c.a[1] * y;
+            state[0] = c.b[1] * x - c.a[0] * y + state[1];
+            self.primed[axis] = true;
+        }
+        let y = c.b[0] * x + state[0];
+        state[0] = c.b[1] * x - c.a[0] * y + state[1];
+        state[1] = c.b[2] * x - c.a[1] * y;
+        y
+    }
+
+    fn design(&self) -> Option<Coefficients> {
+        self.sample_rate_hz
+            .and_then(|rate| Coefficients::design(&self.kind, rate))
+    }
+}
+
+/// Up to `N` [`Biquad`] stages applied in order
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct FilterChain<const N: usize> {
+    stages: [Option<Biquad>; N],
+    len: usize,
+    sample_rate_hz: Option<f32>,
+}
+
+impl<const N: usize> Default for FilterChain<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> FilterChain<N> {
+    pub const fn new() -> Self {
+        FilterChain {
+            stages: [None; N],
+            len: 0,
+            sample_rate_hz: None,
+        }
+    }
+
+    /// Append a stage, returns its index
+    pub fn push(&mut self, kind: FilterKind) -> Result<usize, FilterError> {
+        let mut stage = Biquad::new(kind)?;
+        let slot = self.stages.get_mut(self.len).ok_or(FilterError::Full)?;
+        if let Some(rate) = self.sample_rate_hz {
+            stage.set_sample_rate(rate);
+        }
+        *slot = Some(stage);
+        self.len += 1;
+        Ok(self.len - 1)
+    }
+
+    /// Reconfigure the stage at `index`, e.g. to follow the motor speed
+    pub fn set(&mut self, index: usize, kind: FilterKind) -> Result<(), FilterError> {
+        self.stages[..self.len]
+            .get_mut(index)
+            .and_then(Option::as_mut)
+            .ok_or(FilterError::NoStage)?
+            .set_kind(kind)
+    }
+
+    /// Remove all stages
+    pub fn clear(&mut self) {
+        self.stages = [None; N];
+        self.len = 0;
+    }
+
+    pub fn stages(&self) -> impl Iterator<Item = &Biquad> {
+        self.stages[..self.len].iter().flatten()
+    }
+
+    pub fn sample_rate_hz(&self) -> Option<f32> {
+        self.sample_rate_hz
+    }
+
+    /// Recompute all stages if the rate differs from the current one
+    pub fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        if self.sample_rate_hz != Some(sample_rate_hz) {
+            self.sample_rate_hz = Some(sample_rate_hz);
+            for stage in self.stages[..self.len].iter_mut().flatten() {
+                stage.set_sample_rate(sample_rate_hz);
+            }
+        }
+    }
+
+    /// Forget the state of all stages
+    pub fn reset(&mut self) {
+        for stage in self.stages[..self.len].iter_mut().flatten() {
+            stage.reset();
+        }
+    }
+
+    pub fn process(&mut self, x: [f32; 3]) -> [f32; 3] {
+        self.stages[..self.len]
+            .iter_mut()
+            .flatten()
+            .fold(x, |x, stage| stage.process(x))
+    }
+}
+
+impl SampleFilter for Biquad {
+    fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        Biquad::set_sample_rate(self, sample_rate_hz);
+    }
+
+    fn process(&mut self, x: [f32; 3]) -> [f32; 3] {
+        Biquad::process(self, x)
+    }
+}
+
+impl<const N: usize> SampleFilter for FilterChain<N> {
+    fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        FilterChain::set_sample_rate(self, sample_rate_hz);
+    }
+
+    fn process(&mut self, x: [f32; 3]) -> [f32; 3] {
+        FilterChain::process(self, x)
+    }
+}
@@ -0,0 +1,306 @@
+use core::f32::consts::PI;
+
+use super::{Biquad, FilterError, FilterKind, SampleFilter};
+
+/// Damping of the sliding DFT, keeps rounding errors from accumulating
+const SDFT_R: f32 = 0.9999;
+
+/// Settings of a [`DynamicNotch`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct DynamicNotchConfig {
+    /// Lowest tracked frequency in Hz
+    pub min_hz: f32,
+    /// Highest tracked frequency in Hz
+    pub max_hz: f32,
+    /// Q of the notches
+    pub q: f32,
+    /// Power a peak needs relative to the median power in the range
+    pub threshold: f32,
+    /// Weight of a new peak estimate in the tracked frequency, 0 to 1
+    pub smoothing: f32,
+    /// Searches of an axis without a matching peak before its notch is
+    /// released
+    pub hold: u16,
+}
+
+impl Default for DynamicNotchConfig {
+    fn default() -> Self {
+        DynamicNotchConfig {
+            min_hz: 100.0,
+            max_hz: 600.0,
+            q: 3.0,
+            threshold: 10.0,
+            smoothing: 0.2,
+            hold: 50,
+        }
+    }
+}
+
+impl DynamicNotchConfig {
+    fn is_valid(&self) -> bool {
+        let positive = |x: f32| x.is_finite() && x > 0.0;
+        positive(self.min_hz)
+            && positive(self.q)
+            && self.max_hz > self.min_hz
+            && self.max_hz.is_finite()
+            && self.threshold.is_finite()
+            && positive(self.smoothing)
+            && self.smoothing <= 1.0
+    }
+}
+
+/// Notch filters following the strongest vibration peaks of each axis
+///
+/// A sliding DFT over the last `N` samples is updated with every sample for
+/// the bins of the configured range. Each call searches one axis for its `P`
+/// strongest peaks, interpolates their frequencies between bins and moves
+/// that axis' notches towards them. A peak within two bins of a tracked
+/// notch moves the nearest one, any other peak starts an idle notch or, if
+/// none is idle, moves the nearest one. A notch is idle until its first peak
+/// is found and is released again after `hold` searches of its axis without
+/// a matching peak.
+///
+/// Feed it the full-rate gyroscope stream, e.g. through
+/// [`Gyroscope::filtered`](crate::gyro_impl::Gyroscope::filtered). The
+/// resolution is the sample rate divided by `N`, so 64 samples at 2 kHz
+/// give 31 Hz bins.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct DynamicNotch<const N: usize, const P: usize> {
+    config: DynamicNotchConfig,
+    sample_rate_hz: Option<f32>,
+    window: [[f32; N]; 3],
+    next: usize,
+    samples: usize,
+    /// Complex bins per axis, only the searched range is kept up to date
+    bins: [[[f32; 2]; N]; 3],
+    twiddle: [[f32; 2]; N],
+    /// Weight of the sample leaving the window, `SDFT_R^N`
+    decay: f32,
+    /// Bins updated per sample, one beyond the range on either side
+    range: (usize, usize),
+    axis: usize,
+    peaks: [[Option<f32>; P]; 3],
+    /// Searches since each notch last matched a peak
+    misses: [[u16; P]; 3],
+    notches: [[Biquad; P]; 3],
+}
+
+impl<const N: usize, const P: usize> DynamicNotch<N, P> {
+    pub fn new(config: DynamicNotchConfig) -> Result<Self, FilterError> {
+        if !config.is_valid() || N < 4 {
+            return Err(FilterError::InvalidParameters);
+        }
+        let notch = Biquad::new(FilterKind::Notch {
+            centre_hz: config.min_hz,
+            q: config.q,
+        })?;
+        let mut twiddle = [[0.0; 2]; N];
+        for (k, t) in twiddle.iter_mut().enumerate() {
+            let (sin, cos) = libm::sincosf(2.0 * PI * k as f32 / N as f32);
+            *t = [SDFT_R * cos, SDFT_R * sin];
+        }
+        Ok(DynamicNotch {
+            config,
+            sample_rate_hz: None,
+            window: [[0.0; N]; 3],
+            next: 0,
+            samples: 0,
+            bins: [[[0.0; 2]; N]; 3],
+            twiddle,
+            decay: libm::powf(SDFT_R, N as f32),
+            range: (1, 0),
+            axis: 0,
+            peaks: [[None; P]; 3],
+            misses: [[0; P]; 3],
+            notches: [[notch; P]; 3],
+        })
+    }
+
+    pub fn config(&self) -> &DynamicNotchConfig {
+        &self.config
+    }
+
+    pub fn sample_rate_hz(&self) -> Option<f32> {
+        self.sample_rate_hz
+    }
+
+    /// Restart the analysis and the notches for a new sample rate
+    pub fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        if self.sample_rate_hz == Some(sample_rate_hz) {
+            return;
+        }
+        self.sample_rate_hz = Some(sample_rate_hz);
+        let bin = |hz: f32| hz * N as f32 / sample_rate_hz;
+        let low = libm::ceilf(bin(self.config.min_hz)).max(1.0) as usize;
+        let high = (libm::floorf(bin(self.config.max_hz)) as usize).min(N / 2 - 1);
+        // Empty if the range lies above Nyquist
+        self.range = if low <= high {
+            (low - 1, high + 1)
+        } else {
+            (1, 0)
+        };
+        for notch in self.notches.iter_mut().flatten() {
+            notch.set_sample_rate(sample_rate_hz);
+        }
+        self.reset();
+    }
+
+    /// Forget the spectrum and the tracked peaks
+    pub fn reset(&mut self) {
+        self.window = [[0.0; N]; 3];
+        self.bins = [[[0.0; 2]; N]; 3];
+        self.next = 0;
+        self.samples = 0;
+        self.axis = 0;
+        self.peaks = [[None; P]; 3];
+        self.misses = [[0; P]; 3];
+        for notch in self.notches.iter_mut().flatten() {
+            notch.reset();
+        }
+    }
+
+    /// Tracked peak frequencies in Hz per notch and axis, `None` for idle
+    /// notches
+    pub fn peaks(&self) -> &[[Option<f32>; P]; 3] {
+        &self.peaks
+    }
+
+    pub fn process(&mut self, x: [f32; 3]) -> [f32; 3] {
+        if self.sample_rate_hz.is_none() {
+            return x;
+        }
+        self.slide(x);
+        if self.samples >= N {
+            self.track(self.axis);
+            self.axis = (self.axis + 1) % 3;
+        }
+
+        let mut y = x;
+        for (axis, y) in y.iter_mut().enumerate() {
+            for (peak, notch) in self.peaks[axis].iter().zip(&mut self.notches[axis]) {
+                if peak.is_some() {
+                    *y = notch.process_axis(axis, *y);
+                }
+            }
+        }
+        y
+    }
+
+    fn slide(&mut self, x: [f32; 3]) {
+        let (low, high) = self.range;
+        for ((window, bins), x) in self.window.iter_mut().zip(&mut self.bins).zip(x) {
+            let old = core::mem::replace(&mut window[self.next], x);
+            let delta = x - self.decay * old;
+            for (bin, [c, s]) in bins[low..=high].iter_mut().zip(&self.twiddle[low..=high]) {
+                let re = bin[0] + delta;
+                let im = bin[1];
+                *bin = [re * c - im * s, re * s + im * c];
+            }
+        }
+        self.next = (self.next + 1) % N;
+        self.samples = self.samples.saturating_add(1);
+    }
+
+    fn track(&mut self, axis: usize) {
+        let Some(rate) = self.sample_rate_hz else {
+            return;
+        };
+        let (low, high) = self.range;
+        if high < low + 2 {
+            return;
+        }
+        let power = |k: usize| {
+            let [re, im] = self.bins[axis][k];
+            re * re + im * im
+        };
+        // The median is not pulled up by the peaks themselves
+        let mut sorted = [0.0f32; N];
+        let sorted = &mut sorted[..high - low - 1];
+        for (s, k) in sorted.iter_mut().zip(low + 1..high) {
+            *s = power(k);
+        }
+        sorted.sort_unstable_by(f32::total_cmp);
+        let floor = sorted[sorted.len() / 2];
+
+        // Strongest local maxima, strongest first
+        let mut found = [(0.0f32, 0.0f32); P];
+        let mut count = 0;
+        for k in low + 1..high {
+            let p = power(k);
+            if p <= power(k - 1) || p < power(k + 1) || p <= self.config.threshold * floor {
+                continue;
+            }
+            let Some(slot) = found[..count]
+                .iter()
+                .position(|&(q, _)| p > q)
+                .or((count < P).then_some(count))
+            else {
+                continue;
+            };
+            found.copy_within(slot..P - 1, slot + 1);
+            // Parabolic interpolation of the magnitude between bins
+            let [a, b, c] = [k - 1, k, k + 1].map(|k| libm::sqrtf(power(k)));
+            let denominator = a - 2.0 * b + c;
+            let offset = if denominator < 0.0 {
+                0.5 * (a - c) / denominator
+            } else {
+                0.0
+            };
+            let hz = (k as f32 + offset) * rate / N as f32;
+            found[slot] = (p, hz.clamp(self.config.min_hz, self.config.max_hz));
+            count = (count + 1).min(P);
+        }
+
+        // Strongest peak first, each notch takes at most one
+        let mut matched = [false; P];
+        let capture = 2.0 * rate / N as f32;
+        for &(_, hz) in &found[..count] {
+            let nearest = (0..P)
+                .filter(|&i| !matched[i])
+                .filter_map(|i| self.peaks[axis][i].map(|old| (i, libm::fabsf(old - hz))))
+                .min_by(|a, b| a.1.total_cmp(&b.1));
+            let idle = (0..P).find(|&i| !matched[i] && self.peaks[axis][i].is_none());
+            let slot = match (nearest, idle) {
+                (Some((i, distance)), _) if distance <= capture => i,
+                (_, Some(i)) => i,
+                (Some((i, _)), None) => i,
+                (None, None) => continue,
+            };
+            matched[slot] = true;
+            let tracked = match self.peaks[axis][slot] {
+                Some(old) => old + self.config.smoothing * (hz - old),
+                None => hz,
+            };
+            self.peaks[axis][slot] = Some(tracked);
+            self.misses[axis][slot] = 0;
+            let _ = self.notches[axis][slot].set_kind(FilterKind::Notch {
+                centre_hz: tracked,
+                q: self.config.q,
+            });
+        }
+
+        for slot in (0..P).filter(|&i| !matched[i]) {
+            if self.peaks[axis][slot].is_none() {
+                continue;
+            }
+            self.misses[axis][slot] = self.misses[axis][slot].saturating_add(1);
+            if self.misses[axis][slot] >= self.config.hold {
+                self.peaks[axis][slot] = None;
+                self.misses[axis][slot] = 0;
+                self.notches[axis][slot].reset();
+            }
+        }
+    }
+}
+
+impl<const N: usize, const P: usize> SampleFilter for DynamicNotch<N, P> {
+    fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        DynamicNotch::set_sample_rate(self, sample_rate_hz);
+    }
+
+    fn process(&mut self, x: [f32; 3]) -> [f32; 3] {
+        DynamicNotch::process(self, x)
+    }
+}
@@ -0,0 +1,364 @@
+//! Orientation estimation from accelerometer and gyroscope samples
+//!
+//! The filters consume [`AccelSample`]s and [`GyroSample`]s as returned by
+//! [`Accelerometer::sample`](crate::acc_impl::Accelerometer::sample) and
+//! [`Gyroscope::sample`](crate::gyro_impl::Gyroscope::sample) and take the
+//! time step from the accelerometer's sensor time. The world frame has Z
+//! pointing up, so a device lying flat reads +1 g on Z and has zero roll
+//! and pitch.
+//!
+//! All filters handle their `update` calls the same way. The first sample
+//! with a usable acceleration only sets roll and pitch from gravity, yaw
+//! starts at zero. Every later sample is integrated over the sensor time
+//! since the previous one. A repeated sample or one after a gap longer than
+//! [`MAX_DT`] only resynchronises the clock. The `update_dt` methods skip
+//! all of this and take the time step from the caller.
+
+use crate::{
+    magnetometer::{MagSample, Magnetometer},
+    sample::AccelSample,
+};
+
+mod ekf;
+mod ins;
+mod linear;
+mod madgwick;
+mod mahony;
+mod preintegration;
+
+pub use ekf::{Ekf, NoiseParams};
+pub use ins::{Ins, InsSettings, ZeroVelocityDetector, ZuptSettings};
+pub use linear::{local_gravity, Frame, GravityRemoval};
+pub use madgwick::Madgwick;
+pub use mahony::Mahony;
+pub use preintegration::{Increment, Preintegrator};
+
+/// Steps longer than this are treated as a gap in the data, the filter is
+/// resynchronised instead of integrating across it
+pub const MAX_DT: f32 = 0.5;
+
+/// Standard gravity in m/s²
+pub const STANDARD_GRAVITY: f32 = 9.80665;
+
+/// Unit quaternion rotating board-frame vectors into the world frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Quaternion {
+    pub w: f32,
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Quaternion {
+    pub const IDENTITY: Quaternion = Quaternion {
+        w: 1.0,
+        x: 0.0,
+        y: 0.0,
+        z: 0.0,
+    };
+
+    /// Orientation with the given Euler angles in radians
+    pub fn from_euler(angles: &EulerAngles) -> Self {
+        let (sr, cr) = libm::sincosf(angles.roll * 0.5);
+        let (sp, cp) = libm::sincosf(angles.pitch * 0.5);
+        let (sy, cy) = libm::sincosf(angles.yaw * 0.5);
+        Quaternion {
+            w: cr * cp * cy + sr * sp * sy,
+            x: sr * cp * cy - cr * sp * sy,
+            y: cr * sp * cy + sr * cp * sy,
+            z: cr * cp * sy - sr * sp * cy,
+        }
+    }
+
+    /// Level orientation with zero yaw whose gravity matches `acc`
+    pub fn from_gravity(acc: [f32; 3]) -> Self {
+        let [ax, ay, az] = acc;
+        Self::from_euler(&EulerAngles {
+            roll: libm::atan2f(ay, az),
+            pitch: libm::atan2f(-ax, libm::sqrtf(ay * ay + az * az)),
+            yaw: 0.0,
+        })
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Quaternion {
+            w: self.w,
+            x: -self.x,
+            y: -self.y,
+            z: -self.z,
+        }
+    }
+
+    pub fn norm(&self) -> f32 {
+        libm::sqrtf(self.w * self.w + self.x * self.x + self.y * self.y + self.z * self.z)
+    }
+
+    /// Scale to unit length, a degenerate quaternion becomes the identity
+    pub fn normalized(&self) -> Self {
+        let norm = self.norm();
+        if !norm.is_finite() || norm <= f32::EPSILON {
+            return Self::IDENTITY;
+        }
+        Quaternion {
+            w: self.w / norm,
+            x: self.x / norm,
+            y: self.y / norm,
+            z: self.z / norm,
+        }
+    }
+
+    /// Hamilton product `self ⊗ rhs`
+    pub fn mul(&self, rhs: &Quaternion) -> Self {
+        Quaternion {
+            w: self.w * rhs.w - self.x * rhs.x - self.y * rhs.y - self.z * rhs.z,
+            x: self.w * rhs.x + self.x * rhs.w + self.y * rhs.z - self.z * rhs.y,
+            y: self.w * rhs.y - self.x * rhs.z + self.y * rhs.w + self.z * rhs.x,
+            z: self.w * rhs.z + self.x * rhs.y - self.y * rhs.x + self.z * rhs.w,
+        }
+    }
+
+    /// Rotate a board-frame vector into the world frame
+    pub fn rotate(&self, v: [f32; 3]) -> [f32; 3] {
+        let m = self.matrix();
+        crate::math::mat3_vec(&m, v)
+    }
+
+    /// Rotate a world-frame vector into the board frame
+    pub fn rotate_inverse(&self, v: [f32; 3]) -> [f32; 3] {
+        self.conjugate().rotate(v)
+    }
+
+    /// Rotation matrix taking board-frame vectors into the world frame
+    pub fn matrix(&self) -> [[f32; 3]; 3] {
+        let Quaternion { w, x, y, z } = *self;
+        [
+            [
+                1.0 - 2.0 * (y * y + z * z),
+                2.0 * (x * y - w * z),
+                2.0 * (x * z + w * y),
+            ],
+            [
+                2.0 * (x * y + w * z),
+                1.0 - 2.0 * (x * x + z * z),
+                2.0 * (y * z - w * x),
+            ],
+            [
+                2.0 * (x * z - w * y),
+                2.0 * (y * z + w * x),
+                1.0 - 2.0 * (x * x + y * y),
+            ],
+        ]
+    }
+
+    /// Roll, pitch and yaw in radians, ZYX convention
+    pub fn euler(&self) -> EulerAngles {
+        let Quaternion { w, x, y, z } = *self;
+        let sin_pitch = (2.0 * (w * y - x * z)).clamp(-1.0, 1.0);
+        EulerAngles {
+            roll: libm::atan2f(2.0 * (w * x + y * z), 1.0 - 2.0 * (x * x + y * y)),
+            pitch: libm::asinf(sin_pitch),
+            yaw: libm::atan2f(2.0 * (w * z + x * y), 1.0 - 2.0 * (y * y + z * z)),
+        }
+    }
+
+    /// Unit vector of the accelerometer reading at rest, board frame
+    pub fn gravity(&self) -> [f32; 3] {
+        let Quaternion { w, x, y, z } = *self;
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            1.0 - 2.0 * (x * x + y * y),
+        ]
+    }
+
+    /// Advance by the body rate `gyro` in rad/s over `dt` seconds
+    pub(crate) fn integrate(&self, gyro: [f32; 3], dt: f32) -> Self {
+        let dq = self.mul(&Quaternion {
+            w: 0.0,
+            x: gyro[0],
+            y: gyro[1],
+            z: gyro[2],
+        });
+        Quaternion {
+            w: self.w + 0.5 * dq.w * dt,
+            x: self.x + 0.5 * dq.x * dt,
+            y: self.y + 0.5 * dq.y * dt,
+            z: self.z + 0.5 * dq.z * dt,
+        }
+        .normalized()
+    }
+}
+
+/// Orientation as roll about X, pitch about Y and yaw about Z in radians
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct EulerAngles {
+    pub roll: f32,
+    pub pitch: f32,
+    pub yaw: f32,
+}
+
+impl EulerAngles {
+    pub fn to_degrees(&self) -> EulerAngles {
+        EulerAngles {
+            roll: self.roll.to_degrees(),
+            pitch: self.pitch.to_degrees(),
+            yaw: self.yaw.to_degrees(),
+        }
+    }
+}
+
+/// Yaw in radians from a calibrated board-frame field `mag`, see
+/// [`MagCalibration`](crate::magnetometer::MagCalibration)
+///
+/// Roll and pitch are taken from `attitude` to level the field. The result is
+/// zero with board X pointing to magnetic north and grows counter-clockwise
+/// seen from above, add the declination for true north. It can be passed to
+/// [`Mahony::update_with_yaw`] or [`Ekf::correct_yaw`], [`MagSettings`] does
+/// this for raw [`MagSample`]s. Returns `None` if the horizontal field
+/// vanishes.
+pub fn magnetic_yaw(attitude: &Quaternion, mag: [f32; 3]) -> Option<f32> {
+    let [x, y, _] = attitude.rotate(mag);
+    if libm::sqrtf(x * x + y * y) <= f32::EPSILON {
+        return None;
+    }
+    Some(wrap_pi(attitude.euler().yaw - libm::atan2f(y, x)))
+}
+
+/// How the filters use readings of an external [`Magnetometer`]
+///
+/// Readings are corrected with [`Magnetometer::calibration`] and compared
+/// with the IMU sample they are fused with by their timestamps, which must
+/// come from the same µs clock. A reading further than `max_age_us` from the
+/// IMU sample in either direction is dropped, it would pull yaw towards a
+/// heading the board has already turned away from.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct MagSettings {
+    /// Largest time between a reading and the IMU sample in µs
+    pub max_age_us: u32,
+    /// Uncertainty of the magnetic yaw in rad, used by [`Ekf::correct_mag`]
+    pub yaw_std: f32,
+}
+
+impl Default for MagSettings {
+    /// Readings up to 50 ms old with 5° heading noise
+    fn default() -> Self {
+        MagSettings {
+            max_age_us: 50_000,
+            yaw_std: 5.0f32.to_radians(),
+        }
+    }
+}
+
+impl MagSettings {
+    /// Yaw in radians from the raw `sample` of `magnetometer` for the
+    /// attitude at `imu_time_us`, see [`magnetic_yaw`]
+    ///
+    /// Returns `None` if the reading is too far from the IMU time or the
+    /// horizontal field vanishes.
+    pub fn yaw<M: Magnetometer>(
+        &self,
+        magnetometer: &M,
+        sample: &MagSample,
+        attitude: &Quaternion,
+        imu_time_us: u32,
+    ) -> Option<f32> {
+        let age = sample.age_us(imu_time_us) as i32;
+        if age.unsigned_abs() > self.max_age_us {
+            return None;
+        }
+        magnetic_yaw(attitude, magnetometer.calibration().apply(sample.xyz))
+    }
+}
+
+/// What a filter does with a sample pair, see [`SampleClock::step`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Step {
+    /// First usable sample, level the attitude to this unit gravity vector
+    Init([f32; 3]),
+    /// Integrate over this many seconds
+    Integrate(f32),
+    /// Nothing to integrate
+    Skip,
+}
+
+/// Timing and initialisation shared by the filters' `update` methods
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub(crate) struct SampleClock {
+    last: Option<AccelSample>,
+    initialised: bool,
+}
+
+impl SampleClock {
+    /// Advance to `accel`
+    ///
+    /// Returns [`Step::Init`] until an accelerometer sample with a usable
+    /// magnitude arrives, then the seconds since the previous sample, or
+    /// [`Step::Skip`] for a repeated sample or a gap longer than [`MAX_DT`].
+    pub(crate) fn step(&mut self, accel: &AccelSample) -> Step {
+        let dt = self.last.map(|last| accel.seconds_since(&last));
+        self.last = Some(*accel);
+        if !self.initialised {
+            return match normalize3(accel.xyz) {
+                Some(acc) => {
+                    self.initialised = true;
+                    Step::Init(acc)
+                }
+                None => Step::Skip,
+            };
+        }
+        match dt {
+            Some(dt) if dt > 0.0 && dt <= MAX_DT => Step::Integrate(dt),
+            _ => Step::Skip,
+        }
+    }
+
+    /// Stop [`SampleClock::step`] from initialising, the filter was updated
+    /// directly
+    pub(crate) fn set_initialised(&mut self) {
+        self.initialised = true;
+    }
+
+    pub(crate) fn reset(&mut self) {
+        *self = Self::default();
+    }
+}
+
+pub(crate) fn normalize3(v: [f32; 3]) -> Option<[f32; 3]> {
+    let norm = crate::math::norm3(v);
+    if !norm.is_finite() || norm <= f32::EPSILON {
+        return None;
+    }
+    Some(v.map(|x| x / norm))
+}
+
+pub(crate) fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+/// Wrap an angle in radians into -π..=π
+pub(crate) fn wrap_pi(angle: f32) -> f32 {
+    let wrapped = libm::remainderf(angle, 2.0 * core::f32::consts::PI);
+    if wrapped.is_finite() {
+        wrapped
+    } else {
+        0.0
+    }
+}
+
+pub(crate) fn dps_to_rad(v: [f32; 3]) -> [f32; 3] {
+    v.map(f32::to_radians)
+}
@@ -0,0 +1,384 @@
+#![allow(clippy::needless_range_loop)]
+
+use crate::{
+    acc_impl::{self, AccelerometerRange},
+    config::{AccelConfig, GyroConfig},
+    gyro_impl::{self, GyroBandwidth, GyroscopeRange},
+    magnetometer::{MagSample, Magnetometer},
+    math::{congruence, identity, symmetrise, white_noise},
+    sample::{AccelSample, GyroSample},
+};
+
+use super::{dps_to_rad, wrap_pi, EulerAngles, MagSettings, Quaternion, SampleClock, Step};
+
+type Mat6 = [[f32; 6]; 6];
+
+/// Noise parameters of the [`Ekf`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct NoiseParams {
+    /// Accelerometer white noise per sample in g
+    pub accel_std: f32,
+    /// Gyroscope white noise per sample in °/s
+    pub gyro_std: f32,
+    /// Gyroscope bias random walk in °/s/√s
+    pub gyro_bias_walk: f32,
+    /// Initial uncertainty of the gyroscope bias in °/s
+    pub initial_bias_std: f32,
+}
+
+impl Default for NoiseParams {
+    /// Noise of the sensors' reset configuration
+    fn default() -> Self {
+        Self::new(
+            AccelerometerRange::Scale6g,
+            40.0,
+            GyroscopeRange::Scale2000,
+            GyroBandwidth::Odr2000Bw532,
+        )
+    }
+}
+
+impl NoiseParams {
+    /// Datasheet noise over the filter bandwidth plus quantisation noise
+    pub fn new(
+        accel_range: AccelerometerRange,
+        accel_bandwidth_hz: f32,
+        gyro_range: GyroscopeRange,
+        gyro_bandwidth: GyroBandwidth,
+    ) -> Self {
+        NoiseParams {
+            accel_std: white_noise(
+                acc_impl::NOISE_DENSITY,
+                accel_bandwidth_hz,
+                accel_range.multiplier(),
+            ),
+            gyro_std: white_noise(
+                gyro_impl::NOISE_DENSITY,
+                gyro_bandwidth.bandwidth_hz(),
+                gyro_range.multiplier(),
+            ),
+            gyro_bias_walk: 5e-4,
+            initial_bias_std: 1.0,
+        }
+    }
+
+    /// Noise of a configuration, `None` if a register holds a reserved value
+    pub fn from_config(acc: &AccelConfig, gyro: &GyroConfig) -> Option<Self> {
+        Some(Self::new(
+            acc.accel_range()?,
+            acc.bandwidth_hz()?,
+            gyro.gyro_range()?,
+            gyro.gyro_bandwidth()?,
+        ))
+    }
+}
+
+/// Error-state extended Kalman filter for attitude and gyroscope bias
+///
+/// The nominal state is the attitude quaternion and the gyroscope bias. The
+/// filter tracks the covariance of a 6-element error state, the attitude
+/// error as a small rotation about the world axes in rad and the bias error
+/// in rad/s. The gyroscope drives the prediction and the accelerometer is used
+/// as a gravity measurement. Its noise is inflated by the deviation of the
+/// magnitude from 1 g and the measurement is skipped entirely beyond the
+/// rejection threshold, so linear acceleration does not tilt the estimate.
+/// Yaw drifts unless a reference is fed to [`Ekf::correct_yaw`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Ekf {
+    q: Quaternion,
+    /// Gyroscope bias in rad/s
+    bias: [f32; 3],
+    p: Mat6,
+    noise: NoiseParams,
+    rejection: f32,
+    accel_rejected: bool,
+    clock: SampleClock,
+}
+
+impl Default for Ekf {
+    fn default() -> Self {
+        Self::new(NoiseParams::default())
+    }
+}
+
+impl Ekf {
+    pub fn new(noise: NoiseParams) -> Self {
+        let mut ekf = Ekf {
+            q: Quaternion::IDENTITY,
+            bias: [0.0; 3],
+            p: [[0.0; 6]; 6],
+            noise,
+            rejection: 0.1,
+            accel_rejected: false,
+            clock: SampleClock::default(),
+        };
+        ekf.reset();
+        ekf
+    }
+
+    pub fn set_noise(&mut self, noise: NoiseParams) {
+        self.noise = noise;
+    }
+
+    pub fn noise(&self) -> &NoiseParams {
+        &self.noise
+    }
+
+    /// Skip accelerometer updates whose magnitude is further than
+    /// `threshold_g` from 1 g, 0.1 g by default
+    pub fn set_rejection_threshold(&mut self, threshold_g: f32) {
+        self.rejection = threshold_g;
+    }
+
+    /// Forget the orientation and the bias estimate
+    pub fn reset(&mut self) {
+        self.q = Quaternion::IDENTITY;
+        self.bias = [0.0; 3];
+        self.p = [[0.0; 6]; 6];
+        // Yaw is relative to the heading at start-up, so it starts as certain
+        // as roll and pitch
+        let attitude_std = 5.0f32.to_radians();
+        let bias_std = self.noise.initial_bias_std.to_radians();
+        for i in 0..3 {
+            self.p[i][i] = attitude_std * attitude_std;
+            self.p[i + 3][i + 3] = bias_std * bias_std;
+        }
+        self.accel_rejected = false;
+        self.clock.reset();
+    }
+
+    /// Feed a sample pair, timed and initialised as described in the
+    /// [module documentation](super)
+    pub fn update(&mut self, accel: &AccelSample, gyro: &GyroSample) {
+        match self.clock.step(accel) {
+            Step::Init(acc) => self.q = Quaternion::from_gravity(acc),
+            Step::Integrate(dt) => self.update_dt(accel.xyz, gyro.xyz, dt),
+            Step::Skip => {}
+        }
+    }
+
+    /// Predict with the angular rate in °/s over `dt` seconds and correct
+    /// with the acceleration in g
+    pub fn update_dt(&mut self, acc: [f32; 3], gyro: [f32; 3], dt: f32) {
+        self.predict(gyro, dt);
+        self.correct(acc);
+    }
+
+    /// Propagate the state with the angular rate in °/s over `dt` seconds
+    pub fn predict(&mut self, gyro: [f32; 3], dt: f32) {
+        let gyro = dps_to_rad(gyro);
+        let w = [0, 1, 2].map(|i| gyro[i] - self.bias[i]);
+        self.q = self.q.integrate(w, dt);
+
+        // Φ = I + F dt with F = [[0, -R], [0, 0]]
+        let mut phi: Mat6 = identity();
+        let rotation = self.q.matrix();
+        for i in 0..3 {
+            for j in 0..3 {
+                phi[i][j + 3] = -rotation[i][j] * dt;
+            }
+        }
+        let mut p = congruence(&phi, &self.p);
+        // The rate is held over the step, so its noise integrates coherently
+        let angle = self.noise.gyro_std.to_radians() * dt;
+        let walk = self.noise.gyro_bias_walk.to_radians();
+        for i in 0..3 {
+            p[i][i] += angle * angle;
+            p[i + 3][i + 3] += walk * walk * dt;
+        }
+        // Without a yaw reference yaw is never observed, keep its variance
+        // from growing without bound
+        let max_yaw_var = core::f32::consts::PI * core::f32::consts::PI;
+        if p[2][2] > max_yaw_var {
+            let scale = libm::sqrtf(max_yaw_var / p[2][2]);
+            for i in 0..6 {
+                p[2][i] *= scale;
+                p[i][2] *= scale;
+            }
+        }
+        self.p = symmetrise(p);
+        self.clock.set_initialised();
+    }
+
+    /// Correct the attitude and bias with the acceleration in g
+    ///
+    /// Returns `false` if the measurement was rejected.
+    pub fn correct(&mut self, acc: [f32; 3]) -> bool {
+        let norm = crate::math::norm3(acc);
+        let deviation = (norm - 1.0).abs();
+        self.accel_rejected =
+            deviation.is_nan() || deviation > self.rejection || norm <= f32::EPSILON;
+        if self.accel_rejected {
+            return false;
+        }
+        let z = acc.map(|a| a / norm);
+        let g = self.q.gravity();
+        let residual = [0, 1, 2].map(|i| z[i] - g[i]);
+        let r = self.noise.accel_std * self.noise.accel_std + deviation * deviation;
+
+        // H = [Rᵀ [e_z×], 0], gravity does not see yaw or the bias
+        let rotation = self.q.matrix();
+        let h = [0, 1, 2].map(|i| [rotation[1][i], -rotation[0][i], 0.0]);
+        let mut hp = [[0.0f32; 6]; 3];
+        for i in 0..3 {
+            for j in 0..6 {
+                hp[i][j] = (0..3).map(|k| h[i][k] * self.p[k][j]).sum();
+            }
+        }
+        let mut s = [[0.0f64; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                let v: f32 = (0..3).map(|k| hp[i][k] * h[j][k]).sum();
+                s[i][j] = f64::from(v);
+            }
+            s[i][i] += f64::from(r);
+        }
+        // Kᵀ = S⁻¹ H P, column by column
+        let mut k = [[0.0f32; 3]; 6];
+        for j in 0..6 {
+            let column = [0, 1, 2].map(|i| f64::from(hp[i][j]));
+            let Some(x) = crate::math::solve(s, column) else {
+                return false;
+            };
+            for i in 0..3 {
+                k[j][i] = x[i] as f32;
+            }
+        }
+
+        let dx: [f32; 6] = k.map(|row| (0..3).map(|i| row[i] * residual[i]).sum());
+        let correction = Quaternion {
+            w: 1.0,
+            x: 0.5 * dx[0],
+            y: 0.5 * dx[1],
+            z: 0.5 * dx[2],
+        };
+        self.q = correction.mul(&self.q).normalized();
+        for i in 0..3 {
+            self.bias[i] += dx[i + 3];
+        }
+
+        // Joseph form P = (I - KH) P (I - KH)ᵀ + r K Kᵀ
+        let mut ikh: Mat6 = identity();
+        for i in 0..6 {
+            for j in 0..3 {
+                ikh[i][j] -= (0..3).map(|l| k[i][l] * h[l][j]).sum::<f32>();
+            }
+        }
+        let mut p = congruence(&ikh, &self.p);
+        for i in 0..6 {
+            for j in 0..6 {
+                p[i][j] += r * (0..3).map(|l| k[i][l] * k[j][l]).sum::<f32>();
+            }
+        }
+        self.p = symmetrise(p);
+        true
+    }
+
+    /// Correct with a yaw reference in radians, e.g. from
+    /// [`magnetic_yaw`](super::magnetic_yaw), with an uncertainty of `std`
+    /// radians
+    ///
+    /// Returns `false` if the measurement was unusable.
+    pub fn correct_yaw(&mut self, yaw: f32, std: f32) -> bool {
+        let residual = wrap_pi(yaw - self.q.euler().yaw);
+        let r = std * std;
+        // A rotation about world Z adds directly to the ZYX yaw, H = e_z
+        let s = self.p[2][2] + r;
+        if !residual.is_finite() || !s.is_finite() || s <= f32::EPSILON {
+            return false;
+        }
+        let k: [f32; 6] = [0, 1, 2, 3, 4, 5].map(|i| self.p[i][2] / s);
+        let correction = Quaternion {
+            w: 1.0,
+            x: 0.5 * k[0] * residual,
+            y: 0.5 * k[1] * residual,
+            z: 0.5 * k[2] * residual,
+        };
+        self.q = correction.mul(&self.q).normalized();
+        for i in 0..3 {
+            self.bias[i] += k[i + 3] * residual;
+        }
+
+        // Joseph form as in `correct`
+        let mut ikh: Mat6 = identity();
+        for i in 0..6 {
+            ikh[i][2] -= k[i];
+        }
+        let mut p = congruence(&ikh, &self.p);
+        for i in 0..6 {
+            for j in 0..6 {
+                p[i][j] += r * k[i] * k[j];
+            }
+        }
+        self.p = symmetrise(p);
+        true
+    }
+
+    /// Correct yaw with a raw magnetometer reading, `imu_time_us` is the
+    /// time of the last IMU sample on the magnetometer's clock
+    ///
+    /// Returns `false` if the reading was dropped as stale or unusable, see
+    /// [`MagSettings`].
+    pub fn correct_mag<M: Magnetometer>(
+        &mut self,
+        magnetometer: &M,
+        sample: &MagSample,
+        imu_time_us: u32,
+        settings: &MagSettings,
+    ) -> bool {
+        match settings.yaw(magnetometer, sample, &self.q, imu_time_us) {
+            Some(yaw) => self.correct_yaw(yaw, settings.yaw_std),
+            None => false,
+        }
+    }
+
+    /// Whether the last accelerometer measurement was rejected
+    pub fn accel_rejected(&self) -> bool {
+        self.accel_rejected
+    }
+
+    pub fn quaternion(&self) -> Quaternion {
+        self.q
+    }
+
+    pub fn euler(&self) -> EulerAngles {
+        self.q.euler()
+    }
+
+    /// Estimated gravity direction in the board frame, +Z when level
+    pub fn gravity(&self) -> [f32; 3] {
+        self.q.gravity()
+    }
+
+    /// Estimated gyroscope bias in °/s in the board frame
+    pub fn bias(&self) -> [f32; 3] {
+        self.bias.map(f32::to_degrees)
+    }
+
+    /// Seed the bias estimate, e.g. from a stored calibration
+    pub fn set_bias(&mut self, bias_dps: [f32; 3]) {
+        self.bias = bias_dps.map(f32::to_radians);
+    }
+
+    /// Error-state covariance
+    ///
+    /// Rows and columns are the attitude error about world X, Y and Z in rad
+    /// followed by the board-frame bias error in rad/s.
+    pub fn covariance(&self) -> &[[f32; 6]; 6] {
+        &self.p
+    }
+
+    /// Standard deviation of the attitude error about world X, Y and Z in rad
+    ///
+    /// The Z component is the yaw uncertainty, which only grows.
+    pub fn attitude_std(&self) -> [f32; 3] {
+        [0, 1, 2].map(|i| libm::sqrtf(self.p[i][i]))
+    }
+
+    /// Standard deviation of the bias estimate in °/s
+    pub fn bias_std(&self) -> [f32; 3] {
+        [3, 4, 5].map(|i| libm::sqrtf(self.p[i][i]).to_degrees())
+    }
+}
@@ -0,0 +1,338 @@
+#![allow(clippy::needless_range_loop)]
+
+use crate::{
+    math::{congruence, identity, norm3, symmetrise},
+    sample::{AccelSample, GyroSample},
+};
+
+use super::{dps_to_rad, normalize3, EulerAngles, Quaternion, SampleClock, Step, STANDARD_GRAVITY};
+
+type Mat9 = [[f32; 9]; 9];
+
+/// Settings of the [`ZeroVelocityDetector`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ZuptSettings {
+    /// Accelerometer noise in g
+    pub accel_std: f32,
+    /// Gyroscope noise in °/s
+    pub gyro_std: f32,
+    /// Test statistic below which the sensor is taken to be at rest
+    pub threshold: f32,
+}
+
+impl Default for ZuptSettings {
+    /// Values commonly used for foot-mounted units
+    fn default() -> Self {
+        ZuptSettings {
+            accel_std: 0.01 / STANDARD_GRAVITY,
+            gyro_std: 0.1,
+            threshold: 3e4,
+        }
+    }
+}
+
+/// Stance detector over the last `N` samples
+///
+/// Implements the SHOE detector of Skog et al.: the sensor is at rest if
+/// the acceleration barely deviates from gravity along its mean direction
+/// and the angular rate is close to zero, both weighted by their noise.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct ZeroVelocityDetector<const N: usize> {
+    accel: [[f32; 3]; N],
+    gyro: [[f32; 3]; N],
+    len: usize,
+    next: usize,
+    settings: ZuptSettings,
+    /// Local gravity in g
+    gravity: f32,
+    statistic: Option<f32>,
+}
+
+impl<const N: usize> ZeroVelocityDetector<N> {
+    /// `gravity` is the local gravity in m/s²
+    pub fn new(settings: ZuptSettings, gravity: f32) -> Self {
+        ZeroVelocityDetector {
+            accel: [[0.0; 3]; N],
+            gyro: [[0.0; 3]; N],
+            len: 0,
+            next: 0,
+            settings,
+            gravity: gravity / STANDARD_GRAVITY,
+            statistic: None,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.len = 0;
+        self.next = 0;
+        self.statistic = None;
+    }
+
+    /// Add acceleration in g and angular rate in °/s, returns whether the
+    /// window is at rest
+    pub fn push(&mut self, acc: [f32; 3], gyro: [f32; 3]) -> bool {
+        if N == 0 {
+            return false;
+        }
+        self.accel[self.next] = acc;
+        self.gyro[self.next] = gyro;
+        self.next = (self.next + 1) % N;
+        self.len = (self.len + 1).min(N);
+        if self.len < N {
+            return false;
+        }
+
+        let mut mean = [0.0; 3];
+        for a in &self.accel {
+            for i in 0..3 {
+                mean[i] += a[i] / N as f32;
+            }
+        }
+        let Some(direction) = normalize3(mean) else {
+            return false;
+        };
+        let accel_var = self.settings.accel_std * self.settings.accel_std;
+        let gyro_var = self.settings.gyro_std * self.settings.gyro_std;
+        let mut sum = 0.0;
+        for (a, w) in self.accel.iter().zip(&self.gyro) {
+            let d = [0, 1, 2].map(|i| a[i] - self.gravity * direction[i]);
+            sum += (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]) / accel_var;
+            sum += (w[0] * w[0] + w[1] * w[1] + w[2] * w[2]) / gyro_var;
+        }
+        let statistic = sum / N as f32;
+        self.statistic = Some(statistic);
+        statistic < self.settings.threshold
+    }
+
+    /// Test statistic of the last full window
+    pub fn statistic(&self) -> Option<f32> {
+        self.statistic
+    }
+}
+
+/// Settings of the [`Ins`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct InsSettings {
+    /// Local gravity in m/s²
+    pub gravity: f32,
+    /// Process noise of the acceleration in m/s² per sample
+    pub accel_std: f32,
+    /// Process noise of the angular rate in °/s per sample
+    pub gyro_std: f32,
+    /// Residual velocity of a stance phase in m/s
+    pub zupt_std: f32,
+    pub detector: ZuptSettings,
+}
+
+impl Default for InsSettings {
+    fn default() -> Self {
+        InsSettings {
+            gravity: STANDARD_GRAVITY,
+            accel_std: 0.5,
+            gyro_std: 0.5,
+            zupt_std: 0.01,
+            detector: ZuptSettings::default(),
+        }
+    }
+}
+
+/// Strapdown inertial navigation with zero-velocity updates
+///
+/// Attitude, velocity and position are integrated from the samples in a
+/// local level frame with Z up, starting at the origin with the attitude
+/// levelled by the first accelerometer sample. Whenever the
+/// [`ZeroVelocityDetector`] reports a stance phase, a 9-state error Kalman
+/// filter (attitude, velocity and position error) takes the velocity as
+/// zero. This bounds the velocity error, corrects roll and pitch through
+/// their coupling with velocity and removes most of the position drift.
+/// Yaw and absolute position remain unobservable.
+///
+/// Nothing depends on the hardware, so [`Ins::update_dt`] can be fed with
+/// synthetic trajectories on the host.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Ins<const N: usize = 3> {
+    q: Quaternion,
+    velocity: [f32; 3],
+    position: [f32; 3],
+    p: Mat9,
+    settings: InsSettings,
+    detector: ZeroVelocityDetector<N>,
+    stationary: bool,
+    clock: SampleClock,
+}
+
+impl<const N: usize> Default for Ins<N> {
+    fn default() -> Self {
+        Self::new(InsSettings::default())
+    }
+}
+
+impl<const N: usize> Ins<N> {
+    pub fn new(settings: InsSettings) -> Self {
+        Ins {
+            q: Quaternion::IDENTITY,
+            velocity: [0.0; 3],
+            position: [0.0; 3],
+            p: [[0.0; 9]; 9],
+            settings,
+            detector: ZeroVelocityDetector::new(settings.detector, settings.gravity),
+            stationary: false,
+            clock: SampleClock::default(),
+        }
+    }
+
+    /// Start again at the origin, the next sample levels the attitude
+    pub fn reset(&mut self) {
+        *self = Self::new(self.settings);
+    }
+
+    /// Feed a sample pair, timed and initialised as described in the
+    /// [module documentation](super)
+    ///
+    /// Returns whether the sensor is at rest.
+    pub fn update(&mut self, accel: &AccelSample, gyro: &GyroSample) -> bool {
+        match self.clock.step(accel) {
+            Step::Init(acc) => {
+                self.q = Quaternion::from_gravity(acc);
+                false
+            }
+            Step::Integrate(dt) => self.update_dt(accel.xyz, gyro.xyz, dt),
+            Step::Skip => self.stationary,
+        }
+    }
+
+    /// Propagate with acceleration in g and angular rate in °/s over `dt`
+    /// seconds and apply a zero-velocity update if at rest
+    pub fn update_dt(&mut self, acc: [f32; 3], gyro: [f32; 3], dt: f32) -> bool {
+        self.q = self.q.integrate(dps_to_rad(gyro), dt);
+        let force = self.q.rotate(acc.map(|a| a * STANDARD_GRAVITY));
+        let accel = [force[0], force[1], force[2] - self.settings.gravity];
+        for i in 0..3 {
+            self.position[i] += self.velocity[i] * dt + 0.5 * accel[i] * dt * dt;
+            self.velocity[i] += accel[i] * dt;
+        }
+
+        // δθ' = 0, δv' = -[f×] δθ, δp' = δv, errors in the level frame
+        let mut phi: Mat9 = identity();
+        let [fx, fy, fz] = force;
+        let skew_f = [[0.0, -fz, fy], [fz, 0.0, -fx], [-fy, fx, 0.0]];
+        for i in 0..3 {
+            for j in 0..3 {
+                phi[3 + i][j] = -skew_f[i][j] * dt;
+            }
+            phi[6 + i][3 + i] = dt;
+        }
+        let mut p = congruence(&phi, &self.p);
+        let angle = self.settings.gyro_std.to_radians() * dt;
+        let velocity = self.settings.accel_std * dt;
+        for i in 0..3 {
+            p[i][i] += angle * angle;
+            p[3 + i][3 + i] += velocity * velocity;
+        }
+        self.p = symmetrise(p);
+        self.clock.set_initialised();
+
+        self.stationary = self.detector.push(acc, gyro);
+        if self.stationary {
+            self.zupt();
+        }
+        self.stationary
+    }
+
+    /// Take the current velocity as zero, e.g. from an external stance
+    /// sensor
+    pub fn zupt(&mut self) {
+        let r = self.settings.zupt_std * self.settings.zupt_std;
+        // H = [0, I, 0], so S = P_vv + R and K = P[:, v] S⁻¹
+        let mut s = [[0.0f64; 3]; 3];
+        for i in 0..3 {
+            for j in 0..3 {
+                s[i][j] = f64::from(self.p[3 + i][3 + j]);
+            }
+            s[i][i] += f64::from(r);
+        }
+        let mut k = [[0.0f32; 3]; 9];
+        for row in 0..9 {
+            let b = [0, 1, 2].map(|j| f64::from(self.p[row][3 + j]));
+            let Some(x) = crate::math::solve(s, b) else {
+                return;
+            };
+            k[row] = x.map(|v| v as f32);
+        }
+
+        let residual = self.velocity.map(|v| -v);
+        let dx: [f32; 9] = k.map(|row| (0..3).map(|i| row[i] * residual[i]).sum());
+        let correction = Quaternion {
+            w: 1.0,
+            x: 0.5 * dx[0],
+            y: 0.5 * dx[1],
+            z: 0.5 * dx[2],
+        };
+        self.q = correction.mul(&self.q).normalized();
+        for i in 0..3 {
+            self.velocity[i] += dx[3 + i];
+            self.position[i] += dx[6 + i];
+        }
+
+        // Joseph form P = (I - KH) P (I - KH)ᵀ + r K Kᵀ
+        let mut ikh: Mat9 = identity();
+        for i in 0..9 {
+            for j in 0..3 {
+                ikh[i][3 + j] -= k[i][j];
+            }
+        }
+        let mut p = congruence(&ikh, &self.p);
+        for i in 0..9 {
+            for j in 0..9 {
+                p[i][j] += r * (0..3).map(|l| k[i][l] * k[j][l]).sum::<f32>();
+            }
+        }
+        self.p = symmetrise(p);
+    }
+
+    /// Whether the last sample was detected as a stance phase
+    pub fn is_stationary(&self) -> bool {
+        self.stationary
+    }
+
+    /// Position in m in the level frame
+    pub fn position(&self) -> [f32; 3] {
+        self.position
+    }
+
+    pub fn set_position(&mut self, position: [f32; 3]) {
+        self.position = position;
+    }
+
+    /// Velocity in m/s in the level frame
+    pub fn velocity(&self) -> [f32; 3] {
+        self.velocity
+    }
+
+    /// Horizontal speed in m/s
+    pub fn speed(&self) -> f32 {
+        norm3([self.velocity[0], self.velocity[1], 0.0])
+    }
+
+    pub fn quaternion(&self) -> Quaternion {
+        self.q
+    }
+
+    pub fn euler(&self) -> EulerAngles {
+        self.q.euler()
+    }
+
+    /// Error covariance of attitude in rad, velocity in m/s and position in
+    /// m, all about the level frame axes
+    pub fn covariance(&self) -> &[[f32; 9]; 9] {
+        &self.p
+    }
+
+    pub fn detector(&self) -> &ZeroVelocityDetector<N> {
+        &self.detector
+    }
+}
@@ -0,0 +1,85 @@
+use super::{Quaternion, STANDARD_GRAVITY};
+
+/// Frame a linear acceleration is expressed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Frame {
+    /// Board axes after mounting
+    Body,
+    /// Level frame with Z up and the yaw of the attitude estimate
+    #[default]
+    World,
+}
+
+/// Removes gravity from accelerometer readings
+///
+/// Combines readings in g, e.g. from
+/// [`Accelerometer::xyz`](crate::acc_impl::Accelerometer::xyz), with the
+/// attitude of any of the filters in this module and returns the
+/// acceleration caused by motion in m/s². Attitude errors leak gravity into
+/// the result, 1° of tilt shows up as about 0.17 m/s².
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct GravityRemoval {
+    frame: Frame,
+    /// Local gravity in m/s²
+    gravity: f32,
+}
+
+impl Default for GravityRemoval {
+    fn default() -> Self {
+        Self::new(Frame::World, STANDARD_GRAVITY)
+    }
+}
+
+impl GravityRemoval {
+    /// Output in `frame`, `gravity` is the local gravity in m/s²
+    pub fn new(frame: Frame, gravity: f32) -> Self {
+        GravityRemoval { frame, gravity }
+    }
+
+    pub fn set_frame(&mut self, frame: Frame) {
+        self.frame = frame;
+    }
+
+    pub fn frame(&self) -> Frame {
+        self.frame
+    }
+
+    /// Local gravity in m/s², see [`local_gravity`]
+    pub fn set_gravity(&mut self, gravity: f32) {
+        self.gravity = gravity;
+    }
+
+    pub fn gravity(&self) -> f32 {
+        self.gravity
+    }
+
+    /// Linear acceleration in m/s² from the acceleration `acc` in g measured
+    /// at `attitude`
+    pub fn apply(&self, attitude: &Quaternion, acc: [f32; 3]) -> [f32; 3] {
+        let force = acc.map(|a| a * STANDARD_GRAVITY);
+        match self.frame {
+            Frame::Body => {
+                let up = attitude.gravity();
+                [0, 1, 2].map(|i| force[i] - self.gravity * up[i])
+            }
+            Frame::World => {
+                let [x, y, z] = attitude.rotate(force);
+                [x, y, z - self.gravity]
+            }
+        }
+    }
+}
+
+/// Normal gravity in m/s² at `latitude_deg` and `altitude_m` above the
+/// WGS 84 ellipsoid
+///
+/// Somigliana's formula with the free-air correction, within about
+/// 0.001 m/s² of the actual value away from mountains.
+pub fn local_gravity(latitude_deg: f32, altitude_m: f32) -> f32 {
+    let sin = libm::sinf(latitude_deg.to_radians());
+    let sin2 = sin * sin;
+    let normal = 9.780_325 * (1.0 + 0.001_931_853 * sin2) / libm::sqrtf(1.0 - 0.006_694_38 * sin2);
+    normal - 3.086e-6 * altitude_m
+}
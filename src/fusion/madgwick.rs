@@ -0,0 +1,121 @@
+use crate::sample::{AccelSample, GyroSample};
+
+use super::{dps_to_rad, normalize3, EulerAngles, Quaternion, SampleClock, Step};
+
+/// Madgwick gradient-descent orientation filter
+///
+/// The gyroscope is integrated and the result is pulled towards the
+/// orientation whose gravity matches the accelerometer. `beta` sets how hard
+/// it is pulled, in rad/s: larger values converge faster and follow linear
+/// acceleration more, smaller values trust the gyroscope. Madgwick suggests
+/// `sqrt(3/4)` times the expected gyroscope error in rad/s, 0.033 to 0.1 is
+/// typical.
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Madgwick {
+    q: Quaternion,
+    beta: f32,
+    clock: SampleClock,
+}
+
+impl Default for Madgwick {
+    fn default() -> Self {
+        Self::new(0.1)
+    }
+}
+
+impl Madgwick {
+    pub fn new(beta: f32) -> Self {
+        Madgwick {
+            q: Quaternion::IDENTITY,
+            beta,
+            clock: SampleClock::default(),
+        }
+    }
+
+    pub fn set_beta(&mut self, beta: f32) {
+        self.beta = beta;
+    }
+
+    pub fn beta(&self) -> f32 {
+        self.beta
+    }
+
+    /// Forget the orientation, the next sample initialises it again
+    pub fn reset(&mut self) {
+        self.q = Quaternion::IDENTITY;
+        self.clock.reset();
+    }
+
+    /// Feed a sample pair, timed and initialised as described in the
+    /// [module documentation](super)
+    pub fn update(&mut self, accel: &AccelSample, gyro: &GyroSample) {
+        match self.clock.step(accel) {
+            Step::Init(acc) => self.q = Quaternion::from_gravity(acc),
+            Step::Integrate(dt) => self.update_dt(accel.xyz, gyro.xyz, dt),
+            Step::Skip => {}
+        }
+    }
+
+    /// Feed acceleration in g and angular rate in °/s over `dt` seconds
+    pub fn update_dt(&mut self, acc: [f32; 3], gyro: [f32; 3], dt: f32) {
+        let [gx, gy, gz] = dps_to_rad(gyro);
+        let q = self.q;
+        let mut dot = q.mul(&Quaternion {
+            w: 0.0,
+            x: gx,
+            y: gy,
+            z: gz,
+        });
+        dot = Quaternion {
+            w: 0.5 * dot.w,
+            x: 0.5 * dot.x,
+            y: 0.5 * dot.y,
+            z: 0.5 * dot.z,
+        };
+
+        // A free-falling or saturated accelerometer carries no attitude
+        if let Some([ax, ay, az]) = normalize3(acc) {
+            let Quaternion { w, x, y, z } = q;
+            let [gx, gy, gz] = q.gravity();
+            let f = [gx - ax, gy - ay, gz - az];
+            // Jᵀ f with J the Jacobian of the gravity direction over (w, x, y, z)
+            let step = Quaternion {
+                w: -2.0 * y * f[0] + 2.0 * x * f[1],
+                x: 2.0 * z * f[0] + 2.0 * w * f[1] - 4.0 * x * f[2],
+                y: -2.0 * w * f[0] + 2.0 * z * f[1] - 4.0 * y * f[2],
+                z: 2.0 * x * f[0] + 2.0 * y * f[1],
+            };
+            let norm = step.norm();
+            if norm > f32::EPSILON {
+                let k = self.beta / norm;
+                dot.w -= k * step.w;
+                dot.x -= k * step.x;
+                dot.y -= k * step.y;
+                dot.z -= k * step.z;
+            }
+        }
+
+        self.q = Quaternion {
+            w: q.w + dot.w * dt,
+            x: q.x + dot.x * dt,
+            y: q.y + dot.y * dt,
+            z: q.z + dot.z * dt,
+        }
+        .normalized();
+        self.clock.set_initialised();
+    }
+
+    pub fn quaternion(&self) -> Quaternion {
+        self.q
+    }
+
+    pub fn euler(&self) -> EulerAngles {
+        self.q.euler()
+    }
+
+    /// Estimated gravity direction in the board frame, +Z when level
+    pub fn gravity(&self) -> [f32; 3] {
+        self.q.gravity()
+    }
+}
@@ -0,0 +1,159 @@
+use crate::{
+    magnetometer::{MagSample, Magnetometer},
+    sample::{AccelSample, GyroSample},
+};
+
+use super::{
+    cross, dps_to_rad, normalize3, wrap_pi, EulerAngles, MagSettings, Quaternion, SampleClock, Step,
+};
+
+/// Mahony complementary orientation filter with online gyroscope bias
+///
+/// The attitude error seen by the accelerometer, and optionally by an
+/// external yaw reference, is fed back into the angular rate through a PI
+/// controller. `kp` sets how fast the error is corrected in rad/s per
+/// radian, `ki` lets the integral absorb the gyroscope bias. The integral is
+/// exposed as [`Mahony::bias`].
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Mahony {
+    q: Quaternion,
+    kp: f32,
+    ki: f32,
+    /// Integral feedback in rad/s, the negated bias estimate
+    integral: [f32; 3],
+    bias_limit: f32,
+    clock: SampleClock,
+}
+
+impl Default for Mahony {
+    fn default() -> Self {
+        Self::new(1.0, 0.02)
+    }
+}
+
+impl Mahony {
+    pub fn new(kp: f32, ki: f32) -> Self {
+        Mahony {
+            q: Quaternion::IDENTITY,
+            kp,
+            ki,
+            integral: [0.0; 3],
+            bias_limit: 10.0f32.to_radians(),
+            clock: SampleClock::default(),
+        }
+    }
+
+    pub fn set_gains(&mut self, kp: f32, ki: f32) {
+        self.kp = kp;
+        self.ki = ki;
+    }
+
+    /// Proportional and integral gain
+    pub fn gains(&self) -> (f32, f32) {
+        (self.kp, self.ki)
+    }
+
+    /// Largest bias the integral may absorb per axis in °/s, 10 by default
+    pub fn set_bias_limit(&mut self, limit_dps: f32) {
+        self.bias_limit = limit_dps.to_radians();
+    }
+
+    /// Estimated gyroscope bias in °/s in the board frame
+    ///
+    /// This is what the filter subtracts from [`GyroSample::xyz`].
+    pub fn bias(&self) -> [f32; 3] {
+        self.integral.map(|i| -i.to_degrees())
+    }
+
+    /// Seed the bias estimate, e.g. from a stored calibration
+    pub fn set_bias(&mut self, bias_dps: [f32; 3]) {
+        self.integral = bias_dps.map(|b| -b.to_radians());
+    }
+
+    /// Forget the orientation and the bias estimate
+    pub fn reset(&mut self) {
+        self.q = Quaternion::IDENTITY;
+        self.integral = [0.0; 3];
+        self.clock.reset();
+    }
+
+    /// Feed a sample pair, timed and initialised as described in the
+    /// [module documentation](super)
+    pub fn update(&mut self, accel: &AccelSample, gyro: &GyroSample) {
+        self.update_with_yaw(accel, gyro, None);
+    }
+
+    /// As [`Mahony::update`], also correcting yaw towards `yaw` in radians,
+    /// e.g. a compass or GNSS heading
+    pub fn update_with_yaw(&mut self, accel: &AccelSample, gyro: &GyroSample, yaw: Option<f32>) {
+        match self.clock.step(accel) {
+            Step::Init(acc) => {
+                let mut angles = Quaternion::from_gravity(acc).euler();
+                angles.yaw = yaw.unwrap_or(0.0);
+                self.q = Quaternion::from_euler(&angles);
+            }
+            Step::Integrate(dt) => self.update_dt(accel.xyz, gyro.xyz, yaw, dt),
+            Step::Skip => {}
+        }
+    }
+
+    /// As [`Mahony::update`], also correcting yaw towards a raw magnetometer
+    /// reading, `imu_time_us` is the time of `accel` on the magnetometer's
+    /// clock
+    ///
+    /// A stale or unusable reading is dropped, see [`MagSettings`].
+    pub fn update_with_mag<M: Magnetometer>(
+        &mut self,
+        accel: &AccelSample,
+        gyro: &GyroSample,
+        magnetometer: &M,
+        sample: &MagSample,
+        imu_time_us: u32,
+        settings: &MagSettings,
+    ) {
+        let yaw = settings.yaw(magnetometer, sample, &self.q, imu_time_us);
+        self.update_with_yaw(accel, gyro, yaw);
+    }
+
+    /// Feed acceleration in g, angular rate in °/s and an optional yaw
+    /// reference in radians over `dt` seconds
+    pub fn update_dt(&mut self, acc: [f32; 3], gyro: [f32; 3], yaw: Option<f32>, dt: f32) {
+        let mut error = [0.0; 3];
+        // A free-falling or saturated accelerometer carries no attitude
+        if let Some(acc) = normalize3(acc) {
+            error = cross(acc, self.q.gravity());
+        }
+        if let Some(yaw) = yaw {
+            // Rotation about world Z expressed in the board frame
+            let yaw_error = wrap_pi(yaw - self.q.euler().yaw);
+            let up = self.q.gravity();
+            for (e, u) in error.iter_mut().zip(up) {
+                *e += yaw_error * u;
+            }
+        }
+
+        if self.ki > 0.0 {
+            for (i, e) in self.integral.iter_mut().zip(error) {
+                *i = (*i + self.ki * e * dt).clamp(-self.bias_limit, self.bias_limit);
+            }
+        }
+        let gyro = dps_to_rad(gyro);
+        let rate = [0, 1, 2].map(|i| gyro[i] + self.kp * error[i] + self.integral[i]);
+        self.q = self.q.integrate(rate, dt);
+        self.clock.set_initialised();
+    }
+
+    pub fn quaternion(&self) -> Quaternion {
+        self.q
+    }
+
+    pub fn euler(&self) -> EulerAngles {
+        self.q.euler()
+    }
+
+    /// Estimated gravity direction in the board frame, +Z when level
+    pub fn gravity(&self) -> [f32; 3] {
+        self.q.gravity()
+    }
+}
@@ -0,0 +1,210 @@
+use crate::sample::{AccelSample, GyroSample};
+
+use super::{cross, dps_to_rad, MAX_DT, STANDARD_GRAVITY};
+
+/// Rotation and velocity change over an interval
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Increment {
+    /// Rotation vector from the board frame at `start_us` to the board
+    /// frame at `end_us` in rad
+    pub delta_angle: [f32; 3],
+    /// Integrated specific force in m/s, expressed in the board frame at
+    /// `start_us`, gravity is not removed
+    pub delta_velocity: [f32; 3],
+    /// Length of the interval in s
+    pub dt: f32,
+    pub start_us: u32,
+    pub end_us: u32,
+}
+
+/// Last sample of one sensor
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+struct Stream {
+    time_us: u32,
+    value: [f32; 3],
+}
+
+impl Stream {
+    /// Linear interpolation towards `next` at `time_us`
+    fn at(&self, next: &Stream, time_us: u32) -> [f32; 3] {
+        let span = next.time_us.wrapping_sub(self.time_us) as f32;
+        let offset = time_us.wrapping_sub(self.time_us) as f32;
+        let w = if span > 0.0 { offset / span } else { 1.0 };
+        [0, 1, 2].map(|i| self.value[i] + (next.value[i] - self.value[i]) * w)
+    }
+}
+
+/// Delta-angle and delta-velocity integrator for full-rate samples
+///
+/// Gyroscope and accelerometer samples are pushed in time order with
+/// timestamps in µs from one shared clock, e.g. a hardware timer latched in
+/// the data-ready interrupts. The two rates need not be related: every
+/// sample closes a segment since the previous sample of either sensor. The
+/// sensor that delivered the sample is interpolated linearly over the
+/// segment, the other one is held at its latest value. Segments are
+/// accumulated with second-order coning and sculling compensation.
+#[derive(Debug, Clone, Copy, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Preintegrator {
+    gyro: Option<Stream>,
+    accel: Option<Stream>,
+    /// End of the last segment
+    time_us: Option<u32>,
+    start_us: Option<u32>,
+    alpha: [f32; 3],
+    coning: [f32; 3],
+    velocity: [f32; 3],
+    sculling: [f32; 3],
+    last_angle: [f32; 3],
+    last_velocity: [f32; 3],
+}
+
+impl Preintegrator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drop all samples and the running increment
+    pub fn reset(&mut self) {
+        *self = Self::default();
+    }
+
+    pub fn push_gyro(&mut self, sample: &GyroSample, time_us: u32) {
+        let next = Stream {
+            time_us,
+            value: dps_to_rad(sample.xyz),
+        };
+        let Some(previous) = self.gyro.replace(next) else {
+            return;
+        };
+        let Some(accel) = self.accel else {
+            return;
+        };
+        let Some(start) = self.segment_start(time_us) else {
+            return;
+        };
+        let rate = previous.at(&next, start);
+        let rate = [0, 1, 2].map(|i| 0.5 * (rate[i] + next.value[i]));
+        self.integrate(rate, accel.value, time_us);
+    }
+
+    pub fn push_accel(&mut self, sample: &AccelSample, time_us: u32) {
+        let next = Stream {
+            time_us,
+            value: sample.xyz.map(|a| a * STANDARD_GRAVITY),
+        };
+        let Some(previous) = self.accel.replace(next) else {
+            return;
+        };
+        let Some(gyro) = self.gyro else {
+            return;
+        };
+        let Some(start) = self.segment_start(time_us) else {
+            return;
+        };
+        let force = previous.at(&next, start);
+        let force = [0, 1, 2].map(|i| 0.5 * (force[i] + next.value[i]));
+        self.integrate(gyro.value, force, time_us);
+    }
+
+    /// Increment since the previous call up to the latest sample
+    ///
+    /// Returns `None` while the increment is empty, before the first
+    /// segment is closed or if no sample arrived since the previous call.
+    pub fn take(&mut self) -> Option<Increment> {
+        let end = self.time_us?;
+        if self.start_us == Some(end) {
+            return None;
+        }
+        self.finish(end)
+    }
+
+    /// Increment since the previous call up to `time_us`, e.g. a camera
+    /// exposure timestamp
+    ///
+    /// Both sensors are held at their latest value from the last sample to
+    /// `time_us`, which should therefore be close to it. The next increment
+    /// starts at `time_us`. A `time_us` before the latest sample ends the
+    /// increment at that sample instead.
+    pub fn take_at(&mut self, time_us: u32) -> Option<Increment> {
+        let (gyro, accel, last) = (self.gyro?, self.accel?, self.time_us?);
+        if (time_us.wrapping_sub(last) as i32) <= 0 {
+            return self.finish(last);
+        }
+        if self.segment_start(time_us).is_some() {
+            self.integrate(gyro.value, accel.value, time_us);
+        }
+        self.finish(time_us)
+    }
+
+    /// Start of the segment ending at `time_us`, `None` if it is empty,
+    /// out of order or follows a gap
+    fn segment_start(&mut self, time_us: u32) -> Option<u32> {
+        let Some(start) = self.time_us else {
+            self.time_us = Some(time_us);
+            self.start_us = Some(time_us);
+            return None;
+        };
+        let dt = time_us.wrapping_sub(start) as f32 * 1e-6;
+        if dt > MAX_DT {
+            // Gap or timestamps running backwards, restart the increment
+            self.alpha = [0.0; 3];
+            self.coning = [0.0; 3];
+            self.velocity = [0.0; 3];
+            self.sculling = [0.0; 3];
+            self.last_angle = [0.0; 3];
+            self.last_velocity = [0.0; 3];
+            self.time_us = Some(time_us);
+            self.start_us = Some(time_us);
+            return None;
+        }
+        (dt > 0.0).then_some(start)
+    }
+
+    fn integrate(&mut self, rate: [f32; 3], force: [f32; 3], time_us: u32) {
+        let Some(start) = self.time_us else {
+            return;
+        };
+        let dt = time_us.wrapping_sub(start) as f32 * 1e-6;
+        let angle = rate.map(|w| w * dt);
+        let velocity = force.map(|f| f * dt);
+
+        // Savage's second-order coning and sculling recursions
+        let alpha = [0, 1, 2].map(|i| self.alpha[i] + self.last_angle[i] / 6.0);
+        let v = [0, 1, 2].map(|i| self.velocity[i] + self.last_velocity[i] / 6.0);
+        let coning = cross(alpha, angle);
+        let sculling_a = cross(alpha, velocity);
+        let sculling_b = cross(v, angle);
+        for i in 0..3 {
+            self.coning[i] += 0.5 * coning[i];
+            self.sculling[i] += 0.5 * (sculling_a[i] + sculling_b[i]);
+            self.alpha[i] += angle[i];
+            self.velocity[i] += velocity[i];
+        }
+        self.last_angle = angle;
+        self.last_velocity = velocity;
+        self.time_us = Some(time_us);
+    }
+
+    fn finish(&mut self, end_us: u32) -> Option<Increment> {
+        let start_us = self.start_us?;
+        let rotation = cross(self.alpha, self.velocity);
+        let increment = Increment {
+            delta_angle: [0, 1, 2].map(|i| self.alpha[i] + self.coning[i]),
+            delta_velocity: [0, 1, 2]
+                .map(|i| self.velocity[i] + 0.5 * rotation[i] + self.sculling[i]),
+            dt: end_us.wrapping_sub(start_us) as f32 * 1e-6,
+            start_us,
+            end_us,
+        };
+        self.alpha = [0.0; 3];
+        self.coning = [0.0; 3];
+        self.velocity = [0.0; 3];
+        self.sculling = [0.0; 3];
+        self.start_us = Some(end_us);
+        self.time_us = Some(end_us);
+        Some(increment)
+    }
+}
@@ -1,9 +1,23 @@
+use embedded_hal_async::delay::DelayNs;
+
 use crate::{
-    interface::{AsyncReadData, AsyncWriteData, I2cInterface, SpiInterface},
+    calibration::{BiasCalibration, GyroBias, Moments, TempModel},
+    config::{GyroConfig, ShadowCheck},
+    filter::SampleFilter,
+    interface::{
+        AsyncReadData, AsyncWriteData, BusStats, I2cInterface, RetryInterface, RetryPolicy,
+        SpiInterface,
+    },
+    orientation::Mounting,
     register_address::{GyroRegisters, GyroSelfTest},
-    Bmi088, Error,
+    sample::GyroSample,
+    Bmi088, Error, Sensor,
 };
 
+/// Typical output noise density from the datasheet in °/s/√Hz
+#[cfg(feature = "fusion")]
+pub(crate) const NOISE_DENSITY: f32 = 0.014;
+
 #[derive(Debug, Clone, Copy, PartialEq, Default)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 #[repr(u8)]
@@ -22,6 +36,17 @@ pub enum GyroscopeRange {
 }
 
 impl GyroscopeRange {
+    pub(crate) const fn from_register(value: u8) -> Option<Self> {
+        match value {
+            0x00 => Some(GyroscopeRange::Scale2000),
+            0x01 => Some(GyroscopeRange::Scale1000),
+            0x02 => Some(GyroscopeRange::Scale500),
+            0x03 => Some(GyroscopeRange::Scale250),
+            0x04 => Some(GyroscopeRange::Scale125),
+            _ => None,
+        }
+    }
+
     pub(crate) fn multiplier(&self) -> f32 {
         match self {
             GyroscopeRange::Scale2000 => 0.061,
@@ -33,31 +58,220 @@ impl GyroscopeRange {
     }
 }
 
+/// Output data rate and filter bandwidth, `GYRO_BANDWIDTH` (0x10)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+#[repr(u8)]
+pub enum GyroBandwidth {
+    /// 2000 Hz ODR, 532 Hz filter bandwidth
+    #[default]
+    Odr2000Bw532 = 0x00,
+    /// 2000 Hz ODR, 230 Hz filter bandwidth
+    Odr2000Bw230 = 0x01,
+    /// 1000 Hz ODR, 116 Hz filter bandwidth
+    Odr1000Bw116 = 0x02,
+    /// 400 Hz ODR, 47 Hz filter bandwidth
+    Odr400Bw47 = 0x03,
+    /// 200 Hz ODR, 23 Hz filter bandwidth
+    Odr200Bw23 = 0x04,
+    /// 100 Hz ODR, 12 Hz filter bandwidth
+    Odr100Bw12 = 0x05,
+    /// 200 Hz ODR, 64 Hz filter bandwidth
+    Odr200Bw64 = 0x06,
+    /// 100 Hz ODR, 32 Hz filter bandwidth
+    Odr100Bw32 = 0x07,
+}
+
+impl GyroBandwidth {
+    /// Decode the register value, bit 7 is ignored
+    pub(crate) const fn from_register(value: u8) -> Option<Self> {
+        match value & 0x7F {
+            0x00 => Some(GyroBandwidth::Odr2000Bw532),
+            0x01 => Some(GyroBandwidth::Odr2000Bw230),
+            0x02 => Some(GyroBandwidth::Odr1000Bw116),
+            0x03 => Some(GyroBandwidth::Odr400Bw47),
+            0x04 => Some(GyroBandwidth::Odr200Bw23),
+            0x05 => Some(GyroBandwidth::Odr100Bw12),
+            0x06 => Some(GyroBandwidth::Odr200Bw64),
+            0x07 => Some(GyroBandwidth::Odr100Bw32),
+            _ => None,
+        }
+    }
+
+    pub const fn odr_hz(&self) -> f32 {
+        match self {
+            GyroBandwidth::Odr2000Bw532 | GyroBandwidth::Odr2000Bw230 => 2000.0,
+            GyroBandwidth::Odr1000Bw116 => 1000.0,
+            GyroBandwidth::Odr400Bw47 => 400.0,
+            GyroBandwidth::Odr200Bw23 | GyroBandwidth::Odr200Bw64 => 200.0,
+            GyroBandwidth::Odr100Bw12 | GyroBandwidth::Odr100Bw32 => 100.0,
+        }
+    }
+
+    /// -3 dB bandwidth of the digital filter
+    pub const fn bandwidth_hz(&self) -> f32 {
+        match self {
+            GyroBandwidth::Odr2000Bw532 => 532.0,
+            GyroBandwidth::Odr2000Bw230 => 230.0,
+            GyroBandwidth::Odr1000Bw116 => 116.0,
+            GyroBandwidth::Odr400Bw47 => 47.0,
+            GyroBandwidth::Odr200Bw23 => 23.0,
+            GyroBandwidth::Odr100Bw12 => 12.0,
+            GyroBandwidth::Odr200Bw64 => 64.0,
+            GyroBandwidth::Odr100Bw32 => 32.0,
+        }
+    }
+}
+
 pub struct Gyroscope<DI> {
     iface: DI,
     gyro_range: GyroscopeRange,
+    shadow: GyroConfig,
+    verify_writes: bool,
+    auto_restore: bool,
+    mounting: Mounting,
+    bias: [f32; 3],
+    temp_model: Option<TempModel>,
+    temperature: Option<f32>,
 }
 
-impl<SPI> Bmi088<SpiInterface<SPI>> {
-    /// Create new instance of the BMI088 accelerometer communicating with SPI.
-    pub fn new_gyro_with_spi(spi: SPI) -> Gyroscope<SpiInterface<SPI>> {
+impl<DI> Gyroscope<DI> {
+    pub(crate) fn from_iface(iface: DI) -> Self {
         Gyroscope {
-            iface: SpiInterface {
-                spi,
-                has_dummy_byte: false,
-            },
+            iface,
             gyro_range: Default::default(),
+            shadow: Default::default(),
+            verify_writes: false,
+            auto_restore: false,
+            mounting: Default::default(),
+            bias: [0.0; 3],
+            temp_model: None,
+            temperature: None,
         }
     }
+
+    /// Read back every configuration write and fail with
+    /// [`Error::VerifyFailed`] if the chip holds a different value.
+    pub fn set_write_verify(&mut self, enabled: bool) {
+        self.verify_writes = enabled;
+    }
+
+    /// Re-apply the shadow configuration when [`Gyroscope::check_shadow`]
+    /// finds that the chip has been reset.
+    pub fn set_auto_restore(&mut self, enabled: bool) {
+        self.auto_restore = enabled;
+    }
+
+    /// Retry failed bus transfers according to `policy`
+    ///
+    /// `delay` is used for the backoff between attempts and for the
+    /// per-attempt timeout.
+    pub fn with_retry<D: DelayNs>(
+        self,
+        delay: D,
+        policy: RetryPolicy,
+    ) -> Gyroscope<RetryInterface<DI, D>> {
+        self.map_iface(|iface| RetryInterface::new(iface, delay, policy))
+    }
+
+    fn map_iface<NI>(self, f: impl FnOnce(DI) -> NI) -> Gyroscope<NI> {
+        Gyroscope {
+            iface: f(self.iface),
+            gyro_range: self.gyro_range,
+            shadow: self.shadow,
+            verify_writes: self.verify_writes,
+            auto_restore: self.auto_restore,
+            mounting: self.mounting,
+            bias: self.bias,
+            temp_model: self.temp_model,
+            temperature: self.temperature,
+        }
+    }
+
+    /// Rotate every sample from the chip frame into the board frame
+    pub fn set_mounting(&mut self, mounting: Mounting) {
+        self.mounting = mounting;
+    }
+
+    pub fn mounting(&self) -> &Mounting {
+        &self.mounting
+    }
+
+    /// Zero-rate offset in °/s (chip frame) subtracted by [`Gyroscope::data`]
+    /// after the temperature model
+    pub fn set_bias(&mut self, bias: [f32; 3]) {
+        self.bias = bias;
+    }
+
+    pub fn bias(&self) -> [f32; 3] {
+        self.bias
+    }
+
+    /// Temperature model applied before the other corrections
+    ///
+    /// It is evaluated at the last valid die temperature, or at the model's
+    /// reference temperature until one has been read.
+    pub fn set_temp_model(&mut self, model: Option<TempModel>) {
+        self.temp_model = model;
+    }
+
+    pub fn temp_model(&self) -> Option<&TempModel> {
+        self.temp_model.as_ref()
+    }
+
+    /// Die temperature in °C, as read by
+    /// [`Accelerometer::temperature`](crate::acc_impl::Accelerometer::temperature)
+    pub fn set_temperature(&mut self, temperature: f32) {
+        self.temperature = Some(temperature);
+    }
+
+    fn compensate(&self, v: [f32; 3]) -> [f32; 3] {
+        match &self.temp_model {
+            Some(model) => model.apply(v, self.temperature.unwrap_or(model.t_ref)),
+            None => v,
+        }
+    }
+
+    /// Configuration the driver has written to the chip
+    pub fn shadow_config(&self) -> &GyroConfig {
+        &self.shadow
+    }
+}
+
+impl<SPI> Bmi088<SpiInterface<SPI>> {
+    /// Create new instance of the BMI088 accelerometer communicating with SPI.
+    pub fn new_gyro_with_spi(spi: SPI) -> Gyroscope<SpiInterface<SPI>> {
+        Gyroscope::from_iface(SpiInterface {
+            spi,
+            has_dummy_byte: false,
+            sensor: Sensor::Gyroscope,
+        })
+    }
 }
 
 impl<I2C> Bmi088<I2cInterface<I2C>> {
     /// Create new instance of the BMI088 accelerometer communicating with I2C.
     pub fn new_gyro_with_i2c(i2c: I2C, address: u8) -> Gyroscope<I2cInterface<I2C>> {
-        Gyroscope {
-            iface: I2cInterface { i2c, address },
-            gyro_range: Default::default(),
-        }
+        Gyroscope::from_iface(I2cInterface {
+            i2c,
+            address,
+            sensor: Sensor::Gyroscope,
+        })
+    }
+}
+
+impl<DI, D> Gyroscope<RetryInterface<DI, D>> {
+    /// Transfer counters of the retry layer
+    pub fn bus_stats(&self) -> &BusStats {
+        &self.iface.stats
+    }
+
+    pub fn reset_bus_stats(&mut self) {
+        self.iface.stats = Default::default();
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.iface.policy = policy;
     }
 }
 
@@ -70,16 +284,65 @@ where
         self.iface.read_register(GyroRegisters::CHIP_ID as _).await
     }
 
+    pub async fn set_range(&mut self, range: GyroscopeRange) -> Result<(), Error<E>> {
+        self.write_config_register(GyroRegisters::RANGE, range as u8)
+            .await?;
+        self.gyro_range = range;
+        Ok(())
+    }
+
+    pub async fn set_power_mode(&mut self, lpm1: u8) -> Result<(), Error<E>> {
+        self.write_config_register(GyroRegisters::LPM1, lpm1).await
+    }
+
     pub async fn set_bandwidth(&mut self, bandwidth: u8) -> Result<(), Error<E>> {
-        self.iface
-            .write_register(GyroRegisters::BANDWIDTH as _, bandwidth)
+        self.write_config_register(GyroRegisters::BANDWIDTH, bandwidth)
             .await
     }
-    
+
+    /// Typed variant of [`Self::set_bandwidth`]
+    pub async fn set_odr(&mut self, bandwidth: GyroBandwidth) -> Result<(), Error<E>> {
+        self.set_bandwidth(bandwidth as u8).await
+    }
+
     pub async fn soft_reset(&mut self) -> Result<(), Error<E>> {
         self.iface
             .write_register(GyroRegisters::SOFTRESET as _, 0xB6)
-            .await
+            .await?;
+        self.shadow = Default::default();
+        self.gyro_range = Default::default();
+        Ok(())
+    }
+
+    async fn write_config_register(
+        &mut self,
+        reg: GyroRegisters,
+        value: u8,
+    ) -> Result<(), Error<E>> {
+        self.iface.write_register(reg as _, value).await?;
+        // Bit 7 of the bandwidth register is read-only and always set
+        let value = match reg {
+            GyroRegisters::BANDWIDTH => value | 0x80,
+            _ => value,
+        };
+        if self.verify_writes {
+            let read = self.iface.read_register(reg as _).await?;
+            if read != value {
+                return Err(Error::VerifyFailed {
+                    sensor: Sensor::Gyroscope,
+                    reg: reg as u8,
+                    wrote: value,
+                    read,
+                });
+            }
+        }
+        match reg {
+            GyroRegisters::RANGE => self.shadow.range = value,
+            GyroRegisters::BANDWIDTH => self.shadow.bandwidth = value,
+            GyroRegisters::LPM1 => self.shadow.lpm1 = value,
+            _ => {}
+        }
+        Ok(())
     }
 
     pub async fn check_sensor(&mut self) -> Result<(), Error<E>> {
@@ -135,20 +398,154 @@ where
         Ok(z_raw)
     }
 
+    /// Raw sample in the board frame
     pub async fn burst_read_xyz_rate(&mut self) -> Result<(i16, i16, i16), Error<E>> {
+        let raw = self.read_chip_rate().await?;
+        let [x, y, z] = self.mounting.apply_raw(raw);
+        Ok((x, y, z))
+    }
+
+    /// Angular rate in °/s in the board frame, bias removed
+    pub async fn data(&mut self) -> Result<(f32, f32, f32), Error<E>> {
+        let [x, y, z] = self.sample().await?.xyz;
+        Ok((x, y, z))
+    }
+
+    /// Angular rate as [`Self::data`]
+    pub async fn sample(&mut self) -> Result<GyroSample, Error<E>> {
+        let rate = self.read_chip_dps().await?;
+        let rate = self.compensate(rate);
+        let rate = [0, 1, 2].map(|i| rate[i] - self.bias[i]);
+        Ok(GyroSample {
+            xyz: self.mounting.apply(rate),
+        })
+    }
+
+    /// [`Self::sample`] passed through `filter`, e.g. a
+    /// [`FilterChain`](crate::filter::FilterChain)
+    ///
+    /// The filter's sample rate follows the ODR of the shadow configuration.
+    pub async fn filtered<F: SampleFilter>(
+        &mut self,
+        filter: &mut F,
+    ) -> Result<GyroSample, Error<E>> {
+        let sample = self.sample().await?;
+        if let Some(bandwidth) = self.shadow.gyro_bandwidth() {
+            filter.set_sample_rate(bandwidth.odr_hz());
+        }
+        Ok(GyroSample {
+            xyz: filter.process(sample.xyz),
+        })
+    }
+
+    /// Estimate the zero-rate offset while the device is at rest
+    ///
+    /// The run is rejected with [`Error::NotStationary`] if any axis varies
+    /// more than `settings.max_std_dev`. On success the bias is applied by
+    /// [`Gyroscope::data`] from then on. It is the offset left after the
+    /// temperature model, so install the model first.
+    pub async fn calibrate_bias(
+        &mut self,
+        settings: &BiasCalibration,
+        delay: &mut impl DelayNs,
+    ) -> Result<GyroBias, Error<E>> {
+        let mut moments = Moments::default();
+        for _ in 0..settings.samples {
+            let rate = self.read_chip_dps().await?;
+            moments.push(self.compensate(rate));
+            delay.delay_us(settings.interval_us).await;
+        }
+        let std_dev = moments.std_dev();
+        let moving = |s: &f32| s.is_nan() || *s > settings.max_std_dev;
+        if std_dev.iter().any(moving) {
+            return Err(Error::NotStationary(Sensor::Gyroscope));
+        }
+        self.bias = moments.mean();
+        Ok(GyroBias {
+            bias: self.bias,
+            std_dev,
+            samples: settings.samples,
+        })
+    }
+
+    async fn read_chip_dps(&mut self) -> Result<[f32; 3], Error<E>> {
+        let raw = self.read_chip_rate().await?;
+        Ok(raw.map(|v| v as f32 * self.gyro_range.multiplier()))
+    }
+
+    async fn read_chip_rate(&mut self) -> Result<[i16; 3], Error<E>> {
         let mut data = [GyroRegisters::RATE_X_LSB as u8 | 0x80, 0, 0, 0, 0, 0, 0];
         self.iface.read_data(&mut data).await?;
         let x_raw = i16::from_le_bytes([data[1], data[2]]);
         let y_raw = i16::from_le_bytes([data[3], data[4]]);
         let z_raw = i16::from_le_bytes([data[5], data[6]]);
-        Ok((x_raw, y_raw, z_raw))
+        Ok([x_raw, y_raw, z_raw])
     }
 
-    pub async fn data(&mut self) -> Result<(f32, f32, f32), Error<E>> {
-        let (x, y, z) = self.burst_read_xyz_rate().await?;
-        let x = x as f32 * self.gyro_range.multiplier();
-        let y = y as f32 * self.gyro_range.multiplier();
-        let z = z as f32 * self.gyro_range.multiplier();
-        Ok((x, y, z))
+    /// Read the configuration registers
+    ///
+    /// The cached range used by [`Self::data`] is refreshed from the chip.
+    pub async fn read_config(&mut self) -> Result<GyroConfig, Error<E>> {
+        let config = GyroConfig {
+            range: self.iface.read_register(GyroRegisters::RANGE as _).await?,
+            bandwidth: self
+                .iface
+                .read_register(GyroRegisters::BANDWIDTH as _)
+                .await?,
+            lpm1: self.iface.read_register(GyroRegisters::LPM1 as _).await?,
+        };
+        if let Some(range) = config.gyro_range() {
+            self.gyro_range = range;
+        }
+        Ok(config)
+    }
+
+    /// Adopt the chip's current configuration as the shadow
+    ///
+    /// Use this instead of [`Self::apply_config`] when attaching to a chip
+    /// that may still be configured, e.g. after an MCU reset.
+    pub async fn sync_shadow(&mut self) -> Result<GyroConfig, Error<E>> {
+        let config = self.read_config().await?;
+        self.shadow = config;
+        Ok(config)
+    }
+
+    /// Compare the chip's configuration with the shadow copy
+    ///
+    /// A mismatch means the gyroscope has been reset behind the driver's
+    /// back. With auto restore enabled the shadow configuration is written
+    /// again.
+    ///
+    /// The shadow starts out with the reset values. Run [`Self::apply_config`]
+    /// or [`Self::sync_shadow`] first, otherwise a chip that kept its
+    /// configuration while only the MCU restarted is taken for reset.
+    pub async fn check_shadow(&mut self) -> Result<ShadowCheck, Error<E>> {
+        if self.read_config().await? == self.shadow {
+            return Ok(ShadowCheck::Consistent);
+        }
+        if !self.auto_restore {
+            return Ok(ShadowCheck::ResetDetected);
+        }
+        let shadow = self.shadow;
+        self.apply_config(&shadow).await?;
+        Ok(ShadowCheck::Restored)
+    }
+
+    /// Write the configuration registers
+    ///
+    /// The power mode is written last, so range and bandwidth reach the chip
+    /// while it is still in normal mode. A value the chip does not accept is
+    /// rejected with [`Error::InvalidConfig`] before anything is written.
+    pub async fn apply_config(&mut self, config: &GyroConfig) -> Result<(), Error<E>> {
+        let range = config
+            .validate()
+            .map_err(|(reg, value)| Error::InvalidConfig {
+                sensor: Sensor::Gyroscope,
+                reg: reg as u8,
+                value,
+            })?;
+        self.set_range(range).await?;
+        self.set_bandwidth(config.bandwidth).await?;
+        self.set_power_mode(config.lpm1).await
     }
 }
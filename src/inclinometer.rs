@@ -0,0 +1,84 @@
+//! Static tilt from averaged accelerometer samples
+//!
+//! See [`Accelerometer::tilt`](crate::acc_impl::Accelerometer::tilt).
+
+use core::f32::consts::PI;
+
+/// Roll and pitch of a device at rest, angles in radians
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Tilt {
+    /// Rotation about board X, -π..=π
+    pub roll: f32,
+    /// Rotation about board Y, -π/2..=π/2
+    pub pitch: f32,
+    /// Angle between board Z and vertical, 0..=π
+    pub inclination: f32,
+    /// One standard deviation of `roll`
+    pub roll_std: f32,
+    /// One standard deviation of `pitch` and `inclination`
+    pub pitch_std: f32,
+    /// Magnitude of the averaged acceleration in g, far from 1 if the
+    /// device was not at rest
+    pub magnitude: f32,
+    pub samples: u16,
+}
+
+impl Tilt {
+    /// Tilt of the mean acceleration `acc` in g whose components each have
+    /// an uncertainty of `std_g`
+    ///
+    /// Pitch is computed against the whole Y-Z projection and stays exact up
+    /// to ±90°. Roll loses resolution as board X approaches vertical, its
+    /// uncertainty grows accordingly. Once the Y-Z projection is within the
+    /// noise, roll is undefined and reported as zero with an uncertainty
+    /// of π.
+    pub fn from_gravity(acc: [f32; 3], std_g: f32, samples: u16) -> Self {
+        let [ax, ay, az] = acc;
+        let magnitude = crate::math::norm3(acc);
+        let ayz = libm::sqrtf(ay * ay + az * az);
+        let (roll, roll_std) = if ayz > std_g && ayz > f32::EPSILON {
+            (libm::atan2f(ay, az), (std_g / ayz).min(PI))
+        } else {
+            (0.0, PI)
+        };
+        let pitch_std = if magnitude > f32::EPSILON {
+            (std_g / magnitude).min(PI)
+        } else {
+            PI
+        };
+        Tilt {
+            roll,
+            pitch: libm::atan2f(-ax, ayz),
+            inclination: libm::atan2f(libm::sqrtf(ax * ax + ay * ay), az),
+            roll_std,
+            pitch_std,
+            magnitude,
+            samples,
+        }
+    }
+
+    pub fn roll_deg(&self) -> f32 {
+        self.roll.to_degrees()
+    }
+
+    pub fn pitch_deg(&self) -> f32 {
+        self.pitch.to_degrees()
+    }
+}
+
+/// Uncertainty in g of the mean of `samples` readings taken over
+/// `duration_s`
+///
+/// Readings closer together than the filter's bandwidth allows are
+/// correlated, their mean is then limited by the noise density over the
+/// averaging time rather than by the number of samples.
+pub(crate) fn mean_std(single_std: f32, density: f32, samples: u16, duration_s: f32) -> f32 {
+    let independent = single_std * single_std / f32::from(samples.max(1));
+    let correlated = if duration_s > 0.0 {
+        density * density / (2.0 * duration_s)
+    } else {
+        single_std * single_std
+    };
+    libm::sqrtf(independent.max(correlated))
+}
@@ -1,16 +1,27 @@
 //! I2C/SPI interfaces
 //! Modeified to https://github.com/eldruin/bmi160-rs/blob/master/src/interface.rs
 
+use core::{
+    future::{poll_fn, Future},
+    pin::pin,
+    task::Poll,
+};
+
 use embedded_hal::spi::Operation;
-use embedded_hal_async::{i2c, spi::SpiDevice};
+use embedded_hal_async::{delay::DelayNs, i2c, spi::SpiDevice};
 
-use crate::{private, Error};
+use crate::{
+    private,
+    register_address::{AccRegisters, GyroRegisters},
+    Access, Context, Error, Sensor,
+};
 
 /// I2C interface
 #[derive(Debug)]
 pub struct I2cInterface<I2C> {
     pub(crate) i2c: I2C,
     pub(crate) address: u8,
+    pub(crate) sensor: Sensor,
 }
 
 /// SPI interface
@@ -18,6 +29,19 @@ pub struct I2cInterface<I2C> {
 pub struct SpiInterface<SPI> {
     pub(crate) spi: SPI,
     pub(crate) has_dummy_byte: bool,
+    pub(crate) sensor: Sensor,
+}
+
+/// Attach the transfer context to a bus error
+fn io_error<E>(sensor: Sensor, access: Access, register: u8) -> impl FnOnce(E) -> Error<E> {
+    move |source| Error::IOError {
+        source,
+        context: Context {
+            sensor,
+            access,
+            register,
+        },
+    }
 }
 
 /// Async Write data
@@ -43,12 +67,19 @@ where
     async fn write_register(&mut self, register: u8, data: u8) -> Result<(), Self::Error> {
         let payload: [u8; 2] = [register, data];
         let addr = self.address;
-        self.i2c.write(addr, &payload).await.map_err(Error::IOError)
+        self.i2c
+            .write(addr, &payload)
+            .await
+            .map_err(io_error(self.sensor, Access::Write, register))
     }
 
     async fn write_data(&mut self, payload: &mut [u8]) -> Result<(), Self::Error> {
         let addr = self.address;
-        self.i2c.write(addr, payload).await.map_err(Error::IOError)
+        let register = payload[0];
+        self.i2c
+            .write(addr, payload)
+            .await
+            .map_err(io_error(self.sensor, Access::Write, register))
     }
 }
 
@@ -60,11 +91,18 @@ where
 
     async fn write_register(&mut self, register: u8, data: u8) -> Result<(), Self::Error> {
         let payload: [u8; 2] = [register, data];
-        self.spi.write(&payload).await.map_err(Error::IOError)
+        self.spi
+            .write(&payload)
+            .await
+            .map_err(io_error(self.sensor, Access::Write, register))
     }
 
     async fn write_data(&mut self, payload: &mut [u8]) -> Result<(), Self::Error> {
-        self.spi.write(payload).await.map_err(Error::IOError)
+        let register = payload[0];
+        self.spi
+            .write(payload)
+            .await
+            .map_err(io_error(self.sensor, Access::Write, register))
     }
 }
 
@@ -79,6 +117,10 @@ pub trait AsyncReadData: private::Sealed {
     /// Read from an u8 register
     async fn read_register(&mut self, register: u8) -> Result<u8, Self::Error>;
     /// Read data. The first element corresponds to the starting address.
+    ///
+    /// The payload follows the SPI frame of the sensor: the accelerometer
+    /// returns a dummy byte first, so its data starts at `payload[2]`, the
+    /// gyroscope's at `payload[1]`. Over I2C the dummy slot is left as is.
     async fn read_data(&mut self, payload: &mut [u8]) -> Result<(), Self::Error>;
 }
 
@@ -94,17 +136,25 @@ where
         self.i2c
             .write_read(addr, &[register], &mut data)
             .await
-            .map_err(Error::IOError)?;
+            .map_err(io_error(self.sensor, Access::Read, register))?;
         Ok(data[0])
     }
 
     async fn read_data(&mut self, payload: &mut [u8]) -> Result<(), Self::Error> {
         let len = payload.len();
         let addr = self.address;
+        // Bit 7 selects reads on SPI only, over I2C it would address a
+        // different register
+        let register = payload[0] & 0x7F;
+        // Keep the accelerometer data where the SPI dummy byte puts it
+        let start = match self.sensor {
+            Sensor::Accelerometer => 2,
+            Sensor::Gyroscope => 1,
+        };
         self.i2c
-            .write_read(addr, &[payload[0]], &mut payload[1..len])
+            .write_read(addr, &[register], &mut payload[start..len])
             .await
-            .map_err(Error::IOError)
+            .map_err(io_error(self.sensor, Access::Read, register))
     }
 }
 
@@ -124,7 +174,7 @@ where
             self.spi
                 .transaction(&mut [write_address, read_data])
                 .await
-                .map_err(Error::IOError)?;
+                .map_err(io_error(self.sensor, Access::Read, register))?;
             Ok(data[1])
         } else {
             let mut data = [register | 0x80, 0];
@@ -132,17 +182,230 @@ where
             self.spi
                 .transaction(&mut [operation])
                 .await
-                .map_err(Error::IOError)?;
+                .map_err(io_error(self.sensor, Access::Read, register))?;
             Ok(data[1])
         }
     }
 
     async fn read_data(&mut self, payload: &mut [u8]) -> Result<(), Self::Error> {
+        let register = payload[0] & 0x7F;
         let operation = Operation::TransferInPlace(payload);
         self.spi
             .transaction(&mut [operation])
             .await
-            .map_err(Error::IOError)?;
+            .map_err(io_error(self.sensor, Access::Read, register))?;
         Ok(())
     }
 }
+
+/// Retry policy for bus transfers
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct RetryPolicy {
+    /// Attempts per operation, including the first one
+    pub max_attempts: u8,
+    /// Delay before the first retry
+    pub backoff_us: u32,
+    /// Factor the delay grows by after every retry
+    pub backoff_factor: u32,
+    /// Retry reads. Every register read by the driver is free of side
+    /// effects, so this is safe.
+    pub retry_reads: bool,
+    /// Retry writes. Writing a configuration register twice is harmless.
+    /// Soft resets are never retried, a repeated one would restart the
+    /// reset sequence.
+    pub retry_writes: bool,
+    /// Abandon a single attempt after this long. Only effective with bus
+    /// implementations that yield while waiting for the hardware.
+    ///
+    /// An abandoned transfer is dropped half way, so the device must be
+    /// cancel safe. A plain `SpiDevice` may be left with chip select held
+    /// low, corrupting every later transfer on the bus.
+    /// [`SharedSpiDevice`](crate::shared_bus::SharedSpiDevice) releases chip
+    /// select when dropped.
+    pub timeout_us: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff_us: 100,
+            backoff_factor: 2,
+            retry_reads: true,
+            retry_writes: true,
+            timeout_us: None,
+        }
+    }
+}
+
+/// Bus transfer counters
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct BusStats {
+    /// Operations requested by the driver
+    pub transfers: u32,
+    /// Attempts repeated after a failure
+    pub retries: u32,
+    /// Attempts abandoned after the timeout
+    pub timeouts: u32,
+    /// Operations that failed after all attempts
+    pub failures: u32,
+}
+
+/// Interface wrapper applying a [`RetryPolicy`] to every transfer
+#[derive(Debug)]
+pub struct RetryInterface<DI, D> {
+    pub(crate) iface: DI,
+    pub(crate) delay: D,
+    pub(crate) policy: RetryPolicy,
+    pub(crate) stats: BusStats,
+}
+
+impl<DI, D> RetryInterface<DI, D> {
+    pub(crate) fn new(iface: DI, delay: D, policy: RetryPolicy) -> Self {
+        Self {
+            iface,
+            delay,
+            policy,
+            stats: Default::default(),
+        }
+    }
+}
+
+/// Whether writing `register` twice differs from writing it once
+fn has_side_effects(sensor: Sensor, register: u8) -> bool {
+    match sensor {
+        Sensor::Accelerometer => register == AccRegisters::SOFTRESET as u8,
+        Sensor::Gyroscope => register == GyroRegisters::SOFTRESET as u8,
+    }
+}
+
+/// Poll `fut` until it completes or `timeout_us` elapses
+async fn with_timeout<F: Future>(
+    delay: &mut impl DelayNs,
+    timeout_us: Option<u32>,
+    fut: F,
+) -> Option<F::Output> {
+    let Some(timeout_us) = timeout_us else {
+        return Some(fut.await);
+    };
+    let mut fut = pin!(fut);
+    let mut timer = pin!(delay.delay_us(timeout_us));
+    poll_fn(|cx| {
+        if let Poll::Ready(output) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        if timer.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(None);
+        }
+        Poll::Pending
+    })
+    .await
+}
+
+/// Run `$op` against `$self.iface` until it succeeds or the policy gives up
+macro_rules! retry {
+    ($self:ident, $retry:expr, $access:expr, $register:expr, $op:expr) => {{
+        let attempts = if $retry {
+            $self.policy.max_attempts.max(1)
+        } else {
+            1
+        };
+        let mut backoff_us = $self.policy.backoff_us;
+        $self.stats.transfers = $self.stats.transfers.wrapping_add(1);
+        let mut attempt = 1;
+        loop {
+            let timeout_us = $self.policy.timeout_us;
+            let result = match with_timeout(&mut $self.delay, timeout_us, $op).await {
+                Some(result) => result,
+                None => {
+                    $self.stats.timeouts = $self.stats.timeouts.wrapping_add(1);
+                    Err(Error::Timeout(Context {
+                        sensor: private::Sealed::sensor(&$self.iface),
+                        access: $access,
+                        register: $register,
+                    }))
+                }
+            };
+            match result {
+                Err(Error::IOError { .. } | Error::Timeout(_)) if attempt < attempts => {
+                    attempt += 1;
+                    $self.stats.retries = $self.stats.retries.wrapping_add(1);
+                    $self.delay.delay_us(backoff_us).await;
+                    backoff_us = backoff_us.saturating_mul($self.policy.backoff_factor);
+                }
+                Err(e) => {
+                    $self.stats.failures = $self.stats.failures.wrapping_add(1);
+                    break Err(e);
+                }
+                Ok(value) => break Ok(value),
+            }
+        }
+    }};
+}
+
+impl<DI, D, E> AsyncWriteData for RetryInterface<DI, D>
+where
+    DI: AsyncWriteData<Error = Error<E>>,
+    D: DelayNs,
+{
+    type Error = Error<E>;
+
+    async fn write_register(&mut self, register: u8, data: u8) -> Result<(), Self::Error> {
+        let sensor = private::Sealed::sensor(&self.iface);
+        retry!(
+            self,
+            self.policy.retry_writes && !has_side_effects(sensor, register),
+            Access::Write,
+            register,
+            self.iface.write_register(register, data)
+        )
+    }
+
+    async fn write_data(&mut self, payload: &mut [u8]) -> Result<(), Self::Error> {
+        let register = payload[0];
+        let sensor = private::Sealed::sensor(&self.iface);
+        retry!(
+            self,
+            self.policy.retry_writes && !has_side_effects(sensor, register),
+            Access::Write,
+            register,
+            self.iface.write_data(payload)
+        )
+    }
+}
+
+impl<DI, D, E> AsyncReadData for RetryInterface<DI, D>
+where
+    DI: AsyncReadData<Error = Error<E>>,
+    D: DelayNs,
+{
+    type Error = Error<E>;
+
+    async fn read_register(&mut self, register: u8) -> Result<u8, Self::Error> {
+        retry!(
+            self,
+            self.policy.retry_reads,
+            Access::Read,
+            register,
+            self.iface.read_register(register)
+        )
+    }
+
+    async fn read_data(&mut self, payload: &mut [u8]) -> Result<(), Self::Error> {
+        // SPI reads transfer in place, so restore the address byte before
+        // every attempt
+        let address = payload[0];
+        retry!(
+            self,
+            self.policy.retry_reads,
+            Access::Read,
+            address & 0x7F,
+            {
+                payload[0] = address;
+                self.iface.read_data(payload)
+            }
+        )
+    }
+}
@@ -7,32 +7,241 @@
 #![no_std]
 #![no_main]
 
-use core::marker::PhantomData;
+use core::{fmt, marker::PhantomData};
 
 pub mod acc_impl;
+pub mod allan;
+pub mod calibration;
+pub mod capture;
+pub mod config;
+pub mod events;
+pub mod filter;
+#[cfg(feature = "fusion")]
+pub mod fusion;
 pub mod gyro_impl;
+pub mod inclinometer;
 pub mod interface;
+pub mod magnetometer;
+mod math;
+pub mod orientation;
+pub mod persistence;
 pub mod register_address;
+pub mod sample;
+pub mod shared_bus;
+pub mod spectrum;
+pub mod statistics;
 
 #[derive(Debug)]
 pub struct Bmi088<DI> {
     _p: PhantomData<DI>,
 }
 
+/// Sensor die of the BMI088
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Sensor {
+    Accelerometer,
+    Gyroscope,
+}
+
+/// Direction of a bus transfer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Where a bus transfer went wrong
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Context {
+    pub sensor: Sensor,
+    pub access: Access,
+    /// First register of the transfer
+    pub register: u8,
+}
+
 #[derive(Debug)]
 #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
 pub enum Error<E> {
-    IOError(E),
+    /// The SPI/I2C transfer failed
+    IOError { source: E, context: Context },
 
+    /// The gyroscope self-test did not report OK
     GyroFunctionUnproper,
 
+    /// The accelerometer had no new data
     NoDrdy,
+
+    /// A configuration register did not hold the written value
+    VerifyFailed {
+        sensor: Sensor,
+        reg: u8,
+        wrote: u8,
+        read: u8,
+    },
+
+    /// A bus transfer did not complete within the retry policy's timeout
+    Timeout(Context),
+
+    /// The device moved during a calibration of this sensor that requires
+    /// it to be at rest
+    NotStationary(Sensor),
+
+    /// A configuration holds a value the register does not accept, nothing
+    /// was written
+    InvalidConfig { sensor: Sensor, reg: u8, value: u8 },
+}
+
+impl<E> Error<E> {
+    /// Sensor the error originates from
+    pub fn sensor(&self) -> Sensor {
+        match self {
+            Error::IOError { context, .. } | Error::Timeout(context) => context.sensor,
+            Error::GyroFunctionUnproper => Sensor::Gyroscope,
+            Error::NoDrdy => Sensor::Accelerometer,
+            Error::NotStationary(sensor)
+            | Error::VerifyFailed { sensor, .. }
+            | Error::InvalidConfig { sensor, .. } => *sensor,
+        }
+    }
+
+    /// Register involved in the failed operation, if any
+    pub fn register(&self) -> Option<u8> {
+        match self {
+            Error::IOError { context, .. } | Error::Timeout(context) => Some(context.register),
+            Error::VerifyFailed { reg, .. } | Error::InvalidConfig { reg, .. } => Some(*reg),
+            Error::GyroFunctionUnproper | Error::NoDrdy | Error::NotStationary(_) => None,
+        }
+    }
+
+    /// Bus transfer context, for errors raised by the interface layer
+    pub fn context(&self) -> Option<&Context> {
+        match self {
+            Error::IOError { context, .. } | Error::Timeout(context) => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Error reported by the SPI/I2C implementation
+    pub fn bus_error(&self) -> Option<&E> {
+        match self {
+            Error::IOError { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+
+    /// The bus did not complete the transfer
+    pub fn is_bus_fault(&self) -> bool {
+        matches!(self, Error::IOError { .. } | Error::Timeout(_))
+    }
+
+    /// The transfer completed but the sensor misbehaved
+    pub fn is_sensor_fault(&self) -> bool {
+        matches!(
+            self,
+            Error::GyroFunctionUnproper | Error::NoDrdy | Error::VerifyFailed { .. }
+        )
+    }
+
+    /// Neither bus nor sensor failed, the device was moved while it had to
+    /// be at rest. Repeat the operation once it is still.
+    pub fn is_motion_fault(&self) -> bool {
+        matches!(self, Error::NotStationary(_))
+    }
 }
 
+impl<E: embedded_hal::spi::Error> Error<E> {
+    /// `embedded-hal` error kind of an SPI fault
+    pub fn spi_kind(&self) -> Option<embedded_hal::spi::ErrorKind> {
+        self.bus_error().map(embedded_hal::spi::Error::kind)
+    }
+}
+
+impl<E: embedded_hal::i2c::Error> Error<E> {
+    /// `embedded-hal` error kind of an I2C fault
+    pub fn i2c_kind(&self) -> Option<embedded_hal::i2c::ErrorKind> {
+        self.bus_error().map(embedded_hal::i2c::Error::kind)
+    }
+}
+
+impl fmt::Display for Sensor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Sensor::Accelerometer => f.write_str("accelerometer"),
+            Sensor::Gyroscope => f.write_str("gyroscope"),
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => f.write_str("read"),
+            Access::Write => f.write_str("write"),
+        }
+    }
+}
+
+impl fmt::Display for Context {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} of register {:#04x}",
+            self.sensor, self.access, self.register
+        )
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for Error<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::IOError { source, context } => write!(f, "{context} failed: {source:?}"),
+            Error::GyroFunctionUnproper => f.write_str("gyroscope self-test failed"),
+            Error::NoDrdy => f.write_str("accelerometer data not ready"),
+            Error::VerifyFailed {
+                sensor,
+                reg,
+                wrote,
+                read,
+            } => write!(
+                f,
+                "{sensor} register {reg:#04x} read back {read:#04x} after writing {wrote:#04x}"
+            ),
+            Error::Timeout(context) => write!(f, "{context} timed out"),
+            Error::NotStationary(sensor) => write!(f, "device moved during {sensor} calibration"),
+            Error::InvalidConfig { sensor, reg, value } => {
+                write!(
+                    f,
+                    "{value:#04x} is not a valid value of {sensor} register {reg:#04x}"
+                )
+            }
+        }
+    }
+}
+
+impl<E: fmt::Debug> core::error::Error for Error<E> {}
+
 mod private {
-    use super::interface;
-    pub trait Sealed {}
+    use super::{interface, Sensor};
+    pub trait Sealed {
+        fn sensor(&self) -> Sensor;
+    }
 
-    impl<SPI> Sealed for interface::SpiInterface<SPI> {}
-    impl<I2C> Sealed for interface::I2cInterface<I2C> {}
+    impl<SPI> Sealed for interface::SpiInterface<SPI> {
+        fn sensor(&self) -> Sensor {
+            self.sensor
+        }
+    }
+    impl<I2C> Sealed for interface::I2cInterface<I2C> {
+        fn sensor(&self) -> Sensor {
+            self.sensor
+        }
+    }
+    impl<DI: Sealed, D> Sealed for interface::RetryInterface<DI, D> {
+        fn sensor(&self) -> Sensor {
+            self.iface.sensor()
+        }
+    }
 }
@@ -0,0 +1,189 @@
+//! External magnetometer input for 9-DoF fusion
+//!
+//! The BMI088 has no magnetometer. Boards that carry one implement
+//! [`Magnetometer`] for its driver and report its [`MagCalibration`]. The
+//! fusion filters take the raw [`MagSample`]s, correct them and turn them
+//! into a yaw reference, see `fusion::MagSettings`.
+
+use crate::{
+    calibration::{fit_ellipsoid, CalibrationError},
+    math::{mat3_vec, norm3},
+    orientation::Mounting,
+};
+
+/// Magnetic field reading with its own timestamp
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct MagSample {
+    /// Field in µT, magnetometer frame
+    pub xyz: [f32; 3],
+    /// Time of the reading in µs, wrapping
+    pub time_us: u32,
+}
+
+impl MagSample {
+    /// Microseconds from this reading to `now_us` on the same clock
+    pub fn age_us(&self, now_us: u32) -> u32 {
+        now_us.wrapping_sub(self.time_us)
+    }
+}
+
+/// Source of magnetometer readings
+///
+/// Timestamps should come from the clock used to stamp the BMI088 samples,
+/// e.g. the timer read in the data-ready interrupts, so stale readings can be
+/// recognised with [`MagSample::age_us`].
+#[allow(async_fn_in_trait)]
+pub trait Magnetometer {
+    type Error;
+
+    /// Latest uncalibrated reading
+    async fn sample(&mut self) -> Result<MagSample, Self::Error>;
+
+    /// Correction the fusion filters apply to the readings, none by default
+    fn calibration(&self) -> MagCalibration {
+        MagCalibration::default()
+    }
+}
+
+/// Hard- and soft-iron correction and alignment with the board frame
+///
+/// A reading `m` in µT is corrected to `alignment * soft_iron * (m - hard_iron)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct MagCalibration {
+    /// Field of magnetised parts moving with the board in µT
+    pub hard_iron: [f32; 3],
+    /// Distortion by soft magnetic materials, symmetric
+    pub soft_iron: [[f32; 3]; 3],
+    /// Rotation from the magnetometer frame into the BMI088 board frame
+    pub alignment: Mounting,
+}
+
+impl Default for MagCalibration {
+    fn default() -> Self {
+        Self {
+            hard_iron: [0.0; 3],
+            soft_iron: [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            alignment: Mounting::default(),
+        }
+    }
+}
+
+impl MagCalibration {
+    /// Corrected field in µT, board frame
+    pub fn apply(&self, m: [f32; 3]) -> [f32; 3] {
+        let centred = [0, 1, 2].map(|i| m[i] - self.hard_iron[i]);
+        self.alignment.apply(mat3_vec(&self.soft_iron, centred))
+    }
+}
+
+/// Outcome of [`MagCalibrator::fit`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct MagFit {
+    /// Correction with the default alignment
+    pub calibration: MagCalibration,
+    /// Strength of the local field in µT
+    pub field: f32,
+    /// RMS deviation of the corrected readings from `field`, relative
+    pub residual_rms: f32,
+    /// Cross-axis soft-iron terms were fitted (nine or more readings)
+    pub soft_iron: bool,
+}
+
+/// Collects readings for a hard- and soft-iron calibration
+///
+/// Rotate the board through as many orientations as possible, ideally
+/// tumbling it, away from magnetic objects and add readings spread over the
+/// motion. The readings lie on an ellipsoid which is fitted by least squares
+/// and mapped back onto a sphere. The fit needs at least six readings and
+/// gets reliable with a few dozen.
+#[derive(Debug, Clone)]
+pub struct MagCalibrator<const N: usize> {
+    readings: [[f32; 3]; N],
+    len: usize,
+}
+
+impl<const N: usize> Default for MagCalibrator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> MagCalibrator<N> {
+    pub const fn new() -> Self {
+        Self {
+            readings: [[0.0; 3]; N],
+            len: 0,
+        }
+    }
+
+    /// Add an uncalibrated reading in µT
+    pub fn add(&mut self, reading: [f32; 3]) -> Result<(), CalibrationError> {
+        let slot = self
+            .readings
+            .get_mut(self.len)
+            .ok_or(CalibrationError::Full)?;
+        *slot = reading;
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn readings(&self) -> &[[f32; 3]] {
+        &self.readings[..self.len]
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Fit the calibration and check that the corrected readings lie within
+    /// `max_residual` (RMS, relative) of the field strength
+    pub fn fit(&self, max_residual: f32) -> Result<MagFit, CalibrationError> {
+        let readings = self.readings();
+        // The hard-iron offset can exceed the field, centre the readings so
+        // the origin lies inside the ellipsoid
+        let mut mean = [0.0f32; 3];
+        for r in readings {
+            for (m, v) in mean.iter_mut().zip(r) {
+                *m += v / readings.len() as f32;
+            }
+        }
+        let mut centred = [[0.0f32; 3]; N];
+        for (c, r) in centred.iter_mut().zip(readings) {
+            *c = [0, 1, 2].map(|i| r[i] - mean[i]);
+        }
+        let (ellipsoid, soft_iron) = fit_ellipsoid(&centred[..readings.len()])?;
+
+        // The matrix maps onto the unit sphere, scale it back to the
+        // volume-preserving radius
+        let [a, b, c] = ellipsoid.matrix;
+        let det = a[0] * (b[1] * c[2] - b[2] * c[1]) - a[1] * (b[0] * c[2] - b[2] * c[0])
+            + a[2] * (b[0] * c[1] - b[1] * c[0]);
+        let field = libm::cbrtf(1.0 / det);
+        let calibration = MagCalibration {
+            hard_iron: [0, 1, 2].map(|i| ellipsoid.offset[i] + mean[i]),
+            soft_iron: ellipsoid.matrix.map(|row| row.map(|m| m * field)),
+            alignment: Mounting::default(),
+        };
+
+        let sum_sq: f32 = readings
+            .iter()
+            .map(|&r| {
+                let e = norm3(calibration.apply(r)) / field - 1.0;
+                e * e
+            })
+            .sum();
+        let residual_rms = libm::sqrtf(sum_sq / readings.len() as f32);
+        if residual_rms.is_nan() || residual_rms > max_residual {
+            return Err(CalibrationError::ResidualTooLarge { rms: residual_rms });
+        }
+        Ok(MagFit {
+            calibration,
+            field,
+            residual_rms,
+            soft_iron,
+        })
+    }
+}
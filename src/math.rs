@@ -0,0 +1,210 @@
+//! Small dense linear algebra helpers for the estimators
+
+#![allow(clippy::needless_range_loop)]
+
+/// Solve `a * x = b` by Gaussian elimination with partial pivoting
+///
+/// Returns `None` if `a` is singular.
+pub(crate) fn solve<const N: usize>(mut a: [[f64; N]; N], mut b: [f64; N]) -> Option<[f64; N]> {
+    let scale = a.iter().flatten().fold(0.0f64, |m, x| m.max(x.abs()));
+    for col in 0..N {
+        let pivot = (col..N).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        if a[pivot][col].abs() <= scale * 1e-13 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..N {
+            let factor = a[row][col] / a[col][col];
+            for k in col..N {
+                a[row][k] -= factor * a[col][k];
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = [0.0; N];
+    for row in (0..N).rev() {
+        let sum: f64 = (row + 1..N).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+/// Least-squares normal equations `AᵀA x = Aᵀb`, accumulated row by row
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct NormalEquations<const N: usize> {
+    ata: [[f64; N]; N],
+    atb: [f64; N],
+}
+
+impl<const N: usize> NormalEquations<N> {
+    pub(crate) const fn new() -> Self {
+        Self {
+            ata: [[0.0; N]; N],
+            atb: [0.0; N],
+        }
+    }
+
+    pub(crate) fn push(&mut self, row: &[f64; N], rhs: f64) {
+        for i in 0..N {
+            for j in 0..N {
+                self.ata[i][j] += row[i] * row[j];
+            }
+            self.atb[i] += row[i] * rhs;
+        }
+    }
+
+    pub(crate) fn solve(&self) -> Option<[f64; N]> {
+        solve(self.ata, self.atb)
+    }
+
+    /// Solve for the `active` unknowns only, the others are pinned to zero
+    pub(crate) fn solve_active(&self, active: &[bool; N]) -> Option<[f64; N]> {
+        let mut ata = self.ata;
+        let mut atb = self.atb;
+        for i in 0..N {
+            if !active[i] {
+                for j in 0..N {
+                    ata[i][j] = 0.0;
+                    ata[j][i] = 0.0;
+                }
+                ata[i][i] = 1.0;
+                atb[i] = 0.0;
+            }
+        }
+        solve(ata, atb)
+    }
+}
+
+/// Eigen decomposition of a symmetric 3×3 matrix by Jacobi rotations
+///
+/// Returns the eigenvalues and the eigenvectors as columns of `v`.
+pub(crate) fn symmetric_eigen(mut a: [[f64; 3]; 3]) -> ([f64; 3], [[f64; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..50 {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        if off < 1e-24 {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q].abs() < 1e-30 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + libm::sqrt(theta * theta + 1.0));
+            let c = 1.0 / libm::sqrt(t * t + 1.0);
+            let s = t * c;
+            for k in 0..3 {
+                let (akp, akq) = (a[k][p], a[k][q]);
+                a[k][p] = c * akp - s * akq;
+                a[k][q] = s * akp + c * akq;
+            }
+            for k in 0..3 {
+                let (apk, aqk) = (a[p][k], a[q][k]);
+                a[p][k] = c * apk - s * aqk;
+                a[q][k] = s * apk + c * aqk;
+            }
+            for row in v.iter_mut() {
+                let (vp, vq) = (row[p], row[q]);
+                row[p] = c * vp - s * vq;
+                row[q] = s * vp + c * vq;
+            }
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+#[cfg(feature = "fusion")]
+pub(crate) fn identity<const N: usize>() -> [[f32; N]; N] {
+    let mut m = [[0.0; N]; N];
+    for i in 0..N {
+        m[i][i] = 1.0;
+    }
+    m
+}
+
+#[cfg(feature = "fusion")]
+pub(crate) fn mat_mul<const N: usize>(a: &[[f32; N]; N], b: &[[f32; N]; N]) -> [[f32; N]; N] {
+    let mut m = [[0.0; N]; N];
+    for i in 0..N {
+        for j in 0..N {
+            m[i][j] = (0..N).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+/// `a * p * aᵀ`, the covariance propagation of a linear map
+#[cfg(feature = "fusion")]
+pub(crate) fn congruence<const N: usize>(a: &[[f32; N]; N], p: &[[f32; N]; N]) -> [[f32; N]; N] {
+    let ap = mat_mul(a, p);
+    let mut m = [[0.0; N]; N];
+    for i in 0..N {
+        for j in 0..N {
+            m[i][j] = (0..N).map(|k| ap[i][k] * a[j][k]).sum();
+        }
+    }
+    m
+}
+
+/// Average a matrix with its transpose to remove rounding asymmetry
+#[cfg(feature = "fusion")]
+pub(crate) fn symmetrise<const N: usize>(mut p: [[f32; N]; N]) -> [[f32; N]; N] {
+    for i in 0..N {
+        for j in i + 1..N {
+            let v = 0.5 * (p[i][j] + p[j][i]);
+            p[i][j] = v;
+            p[j][i] = v;
+        }
+    }
+    p
+}
+
+pub(crate) fn mat3_vec(m: &[[f32; 3]; 3], v: [f32; 3]) -> [f32; 3] {
+    m.map(|row| row[0] * v[0] + row[1] * v[1] + row[2] * v[2])
+}
+
+pub(crate) fn norm3(v: [f32; 3]) -> f32 {
+    libm::sqrtf(v[0] * v[0] + v[1] * v[1] + v[2] * v[2])
+}
+
+/// Standard deviation of white noise of `density` per √Hz over `bandwidth`
+/// Hz plus the quantisation noise of one `lsb`
+pub(crate) fn white_noise(density: f32, bandwidth: f32, lsb: f32) -> f32 {
+    libm::sqrtf(density * density * bandwidth + lsb * lsb / 12.0)
+}
+
+/// In-place radix-2 FFT of complex `[re, im]` values, `N` a power of two
+pub(crate) fn fft<const N: usize>(x: &mut [[f32; 2]; N]) {
+    // Bit-reversed order
+    let mut j = 0;
+    for i in 1..N {
+        let mut bit = N >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            x.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= N {
+        let (sin, cos) = libm::sincos(-2.0 * core::f64::consts::PI / len as f64);
+        for start in (0..N).step_by(len) {
+            // Twiddles by recurrence in f64 to keep the error down
+            let mut w = [1.0f64, 0.0];
+            for k in 0..len / 2 {
+                let [ar, ai] = x[start + k];
+                let [br, bi] = x[start + k + len / 2];
+                let (wr, wi) = (w[0] as f32, w[1] as f32);
+                let t = [br * wr - bi * wi, br * wi + bi * wr];
+                x[start + k] = [ar + t[0], ai + t[1]];
+                x[start + k + len / 2] = [ar - t[0], ai - t[1]];
+                w = [w[0] * cos - w[1] * sin, w[0] * sin + w[1] * cos];
+            }
+        }
+        len <<= 1;
+    }
+}
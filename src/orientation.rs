@@ -0,0 +1,197 @@
+//! Board mounting orientation
+//!
+//! Samples are read in the chip frame. A [`Mounting`] rotates them into the
+//! board frame, either with one of the 24 axis-aligned rotations, which only
+//! swap and negate axes and therefore keep raw integer samples exact, or
+//! with an arbitrary rotation matrix.
+
+use crate::math::mat3_vec;
+
+/// Axis-aligned mounting rotation
+///
+/// Each variant names the signed chip axes that become board X and board Y,
+/// board Z follows from the right-hand rule. `P` is positive and `N` is
+/// negative, so [`AxisRotation::PyNx`] takes board X from chip +Y and board
+/// Y from chip -X, i.e. the chip is rotated 90° clockwise about Z.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum AxisRotation {
+    /// Chip and board frames coincide
+    #[default]
+    PxPy,
+    PxNy,
+    PxPz,
+    PxNz,
+    NxPy,
+    NxNy,
+    NxPz,
+    NxNz,
+    PyPx,
+    PyNx,
+    PyPz,
+    PyNz,
+    NyPx,
+    NyNx,
+    NyPz,
+    NyNz,
+    PzPx,
+    PzNx,
+    PzPy,
+    PzNy,
+    NzPx,
+    NzNx,
+    NzPy,
+    NzNy,
+}
+
+impl AxisRotation {
+    /// All 24 rotations
+    pub const ALL: [AxisRotation; 24] = [
+        AxisRotation::PxPy,
+        AxisRotation::PxNy,
+        AxisRotation::PxPz,
+        AxisRotation::PxNz,
+        AxisRotation::NxPy,
+        AxisRotation::NxNy,
+        AxisRotation::NxPz,
+        AxisRotation::NxNz,
+        AxisRotation::PyPx,
+        AxisRotation::PyNx,
+        AxisRotation::PyPz,
+        AxisRotation::PyNz,
+        AxisRotation::NyPx,
+        AxisRotation::NyNx,
+        AxisRotation::NyPz,
+        AxisRotation::NyNz,
+        AxisRotation::PzPx,
+        AxisRotation::PzNx,
+        AxisRotation::PzPy,
+        AxisRotation::PzNy,
+        AxisRotation::NzPx,
+        AxisRotation::NzNx,
+        AxisRotation::NzPy,
+        AxisRotation::NzNy,
+    ];
+
+    /// Signed chip axes of board X and board Y as `(axis, negative)`
+    const fn xy(self) -> [(usize, bool); 2] {
+        const PX: (usize, bool) = (0, false);
+        const NX: (usize, bool) = (0, true);
+        const PY: (usize, bool) = (1, false);
+        const NY: (usize, bool) = (1, true);
+        const PZ: (usize, bool) = (2, false);
+        const NZ: (usize, bool) = (2, true);
+        match self {
+            AxisRotation::PxPy => [PX, PY],
+            AxisRotation::PxNy => [PX, NY],
+            AxisRotation::PxPz => [PX, PZ],
+            AxisRotation::PxNz => [PX, NZ],
+            AxisRotation::NxPy => [NX, PY],
+            AxisRotation::NxNy => [NX, NY],
+            AxisRotation::NxPz => [NX, PZ],
+            AxisRotation::NxNz => [NX, NZ],
+            AxisRotation::PyPx => [PY, PX],
+            AxisRotation::PyNx => [PY, NX],
+            AxisRotation::PyPz => [PY, PZ],
+            AxisRotation::PyNz => [PY, NZ],
+            AxisRotation::NyPx => [NY, PX],
+            AxisRotation::NyNx => [NY, NX],
+            AxisRotation::NyPz => [NY, PZ],
+            AxisRotation::NyNz => [NY, NZ],
+            AxisRotation::PzPx => [PZ, PX],
+            AxisRotation::PzNx => [PZ, NX],
+            AxisRotation::PzPy => [PZ, PY],
+            AxisRotation::PzNy => [PZ, NY],
+            AxisRotation::NzPx => [NZ, PX],
+            AxisRotation::NzNx => [NZ, NX],
+            AxisRotation::NzPy => [NZ, PY],
+            AxisRotation::NzNy => [NZ, NY],
+        }
+    }
+
+    /// Signed chip axes of board X, Y and Z as `(axis, negative)`
+    const fn axes(self) -> [(usize, bool); 3] {
+        let [(x, x_neg), (y, y_neg)] = self.xy();
+        let z = 3 - x - y;
+        // e_x × e_y = +e_z for cyclic (x, y, z), -e_z otherwise
+        let cyclic = (x + 1) % 3 == y;
+        [(x, x_neg), (y, y_neg), (z, x_neg ^ y_neg ^ !cyclic)]
+    }
+
+    /// Rotate a chip-frame vector into the board frame
+    pub fn apply(self, v: [f32; 3]) -> [f32; 3] {
+        self.axes()
+            .map(|(axis, negative)| if negative { -v[axis] } else { v[axis] })
+    }
+
+    /// Rotate a raw chip-frame sample into the board frame
+    ///
+    /// Exact except for -32768, which has no positive counterpart and
+    /// saturates to 32767 when negated.
+    pub fn apply_raw(self, v: [i16; 3]) -> [i16; 3] {
+        self.axes().map(|(axis, negative)| {
+            if negative {
+                v[axis].saturating_neg()
+            } else {
+                v[axis]
+            }
+        })
+    }
+
+    /// Rotation matrix taking chip-frame vectors into the board frame
+    pub fn matrix(self) -> [[f32; 3]; 3] {
+        let mut m = [[0.0; 3]; 3];
+        for (row, (axis, negative)) in m.iter_mut().zip(self.axes()) {
+            row[axis] = if negative { -1.0 } else { 1.0 };
+        }
+        m
+    }
+}
+
+/// Rotation from the chip frame into the board frame
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Mounting {
+    /// One of the 24 axis-aligned rotations
+    Aligned(AxisRotation),
+    /// Arbitrary rotation matrix, row-major, `board = M * chip`
+    Matrix([[f32; 3]; 3]),
+}
+
+impl Default for Mounting {
+    fn default() -> Self {
+        Mounting::Aligned(AxisRotation::default())
+    }
+}
+
+impl Mounting {
+    /// Rotate a chip-frame vector into the board frame
+    pub fn apply(&self, v: [f32; 3]) -> [f32; 3] {
+        match self {
+            Mounting::Aligned(rotation) => rotation.apply(v),
+            Mounting::Matrix(m) => mat3_vec(m, v),
+        }
+    }
+
+    /// Rotate a raw chip-frame sample into the board frame
+    ///
+    /// Axis-aligned rotations are exact, a matrix result is rounded to the
+    /// nearest LSB and clamped to the `i16` range.
+    pub fn apply_raw(&self, v: [i16; 3]) -> [i16; 3] {
+        match self {
+            Mounting::Aligned(rotation) => rotation.apply_raw(v),
+            Mounting::Matrix(_) => self.apply(v.map(f32::from)).map(|x| {
+                let rounded = if x >= 0.0 { x + 0.5 } else { x - 0.5 };
+                rounded.clamp(i16::MIN as f32, i16::MAX as f32) as i16
+            }),
+        }
+    }
+
+    /// Rotation matrix taking chip-frame vectors into the board frame
+    pub fn matrix(&self) -> [[f32; 3]; 3] {
+        match self {
+            Mounting::Aligned(rotation) => rotation.matrix(),
+            Mounting::Matrix(m) => *m,
+        }
+    }
+}
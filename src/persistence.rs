@@ -0,0 +1,336 @@
+//! Calibration persistence
+//!
+//! [`CalibrationData`] serialises into a fixed-size blob laid out as
+//!
+//! | bytes | content                           |
+//! |-------|-----------------------------------|
+//! | 4     | magic `B088`                      |
+//! | 2     | format version, little endian     |
+//! | 2     | payload length, little endian     |
+//! | n     | payload                           |
+//! | 4     | CRC-32 of everything above        |
+//!
+//! With the `embedded-storage` feature, [`FlashStore`] keeps the blob in a
+//! `NorFlash` region.
+
+use crate::{
+    acc_impl::Accelerometer,
+    calibration::{AccelCalibration, TempModel, MAX_TEMP_DEGREE},
+    gyro_impl::Gyroscope,
+    orientation::{AxisRotation, Mounting},
+};
+
+const MAGIC: [u8; 4] = *b"B088";
+const HEADER_LEN: usize = 8;
+const TEMP_MODEL_LEN: usize = 4 * (1 + 2 * 3 * (MAX_TEMP_DEGREE + 1));
+const PAYLOAD_LEN: usize = 4 * 12 + 4 * 3 + 1 + 2 * TEMP_MODEL_LEN + 2 + 4 * 9;
+
+/// Current blob format version
+pub const FORMAT_VERSION: u16 = 1;
+
+/// Length of a serialised [`CalibrationData`]
+pub const BLOB_LEN: usize = HEADER_LEN + PAYLOAD_LEN + 4;
+
+/// Why a blob was rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum BlobError {
+    /// No calibration blob, e.g. erased flash
+    BadMagic,
+    /// Written by an incompatible version of the format
+    UnsupportedVersion(u16),
+    /// Payload length does not match the version
+    BadLength,
+    /// The checksum does not match, the data is corrupt
+    BadCrc,
+    /// A field holds a value outside its domain
+    InvalidField,
+}
+
+/// Everything needed to restore a board's calibration
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct CalibrationData {
+    pub accel: AccelCalibration,
+    pub accel_temp: Option<TempModel>,
+    /// Gyroscope zero-rate offset in °/s, chip frame
+    pub gyro_bias: [f32; 3],
+    pub gyro_temp: Option<TempModel>,
+    /// Mounting shared by both sensors
+    pub mounting: Mounting,
+}
+
+impl CalibrationData {
+    /// Collect the corrections currently applied by the drivers
+    ///
+    /// The mounting is taken from the accelerometer.
+    pub fn from_drivers<AI, GI>(acc: &Accelerometer<AI>, gyro: &Gyroscope<GI>) -> Self {
+        Self {
+            accel: *acc.calibration(),
+            accel_temp: acc.temp_model().copied(),
+            gyro_bias: gyro.bias(),
+            gyro_temp: gyro.temp_model().copied(),
+            mounting: *acc.mounting(),
+        }
+    }
+
+    /// Install the corrections in both drivers
+    pub fn apply_to<AI, GI>(&self, acc: &mut Accelerometer<AI>, gyro: &mut Gyroscope<GI>) {
+        acc.set_calibration(self.accel);
+        acc.set_temp_model(self.accel_temp);
+        acc.set_mounting(self.mounting);
+        gyro.set_bias(self.gyro_bias);
+        gyro.set_temp_model(self.gyro_temp);
+        gyro.set_mounting(self.mounting);
+    }
+
+    pub fn to_bytes(&self) -> [u8; BLOB_LEN] {
+        let mut blob = [0; BLOB_LEN];
+        blob[..4].copy_from_slice(&MAGIC);
+        blob[4..6].copy_from_slice(&FORMAT_VERSION.to_le_bytes());
+        blob[6..8].copy_from_slice(&(PAYLOAD_LEN as u16).to_le_bytes());
+
+        let mut w = Writer {
+            buf: &mut blob[HEADER_LEN..HEADER_LEN + PAYLOAD_LEN],
+            pos: 0,
+        };
+        w.f32s(&self.accel.offset);
+        for row in &self.accel.matrix {
+            w.f32s(row);
+        }
+        w.f32s(&self.gyro_bias);
+        w.u8(self.accel_temp.is_some() as u8 | (self.gyro_temp.is_some() as u8) << 1);
+        for model in [&self.accel_temp, &self.gyro_temp] {
+            let model = model.unwrap_or(TempModel::identity(0.0));
+            w.f32s(&[model.t_ref]);
+            for coefficients in model.bias.iter().chain(&model.scale) {
+                w.f32s(coefficients);
+            }
+        }
+        match &self.mounting {
+            Mounting::Aligned(rotation) => {
+                let index = AxisRotation::ALL.iter().position(|r| r == rotation);
+                w.u8(0);
+                w.u8(index.unwrap_or(0) as u8);
+                for row in &rotation.matrix() {
+                    w.f32s(row);
+                }
+            }
+            Mounting::Matrix(m) => {
+                w.u8(1);
+                w.u8(0);
+                for row in m {
+                    w.f32s(row);
+                }
+            }
+        }
+
+        let crc = crc32(&blob[..BLOB_LEN - 4]);
+        blob[BLOB_LEN - 4..].copy_from_slice(&crc.to_le_bytes());
+        blob
+    }
+
+    /// Validate and decode a blob
+    pub fn from_bytes(blob: &[u8]) -> Result<Self, BlobError> {
+        if blob.len() < HEADER_LEN || blob[..4] != MAGIC {
+            return Err(BlobError::BadMagic);
+        }
+        let version = u16::from_le_bytes([blob[4], blob[5]]);
+        if version != FORMAT_VERSION {
+            return Err(BlobError::UnsupportedVersion(version));
+        }
+        let len = u16::from_le_bytes([blob[6], blob[7]]) as usize;
+        if len != PAYLOAD_LEN || blob.len() < BLOB_LEN {
+            return Err(BlobError::BadLength);
+        }
+        let crc = u32::from_le_bytes([
+            blob[BLOB_LEN - 4],
+            blob[BLOB_LEN - 3],
+            blob[BLOB_LEN - 2],
+            blob[BLOB_LEN - 1],
+        ]);
+        if crc32(&blob[..BLOB_LEN - 4]) != crc {
+            return Err(BlobError::BadCrc);
+        }
+
+        let mut r = Reader {
+            buf: &blob[HEADER_LEN..HEADER_LEN + PAYLOAD_LEN],
+            pos: 0,
+        };
+        let mut accel = AccelCalibration {
+            offset: r.f32s(),
+            ..Default::default()
+        };
+        for row in accel.matrix.iter_mut() {
+            *row = r.f32s();
+        }
+        let gyro_bias = r.f32s();
+        let flags = r.u8();
+        let mut models = [None; 2];
+        for (i, model) in models.iter_mut().enumerate() {
+            let [t_ref] = r.f32s();
+            let mut m = TempModel::identity(t_ref);
+            for coefficients in m.bias.iter_mut().chain(m.scale.iter_mut()) {
+                *coefficients = r.f32s();
+            }
+            if flags & (1 << i) != 0 {
+                *model = Some(m);
+            }
+        }
+        let mounting = match r.u8() {
+            0 => {
+                let index = r.u8() as usize;
+                let rotation = AxisRotation::ALL
+                    .get(index)
+                    .ok_or(BlobError::InvalidField)?;
+                Mounting::Aligned(*rotation)
+            }
+            1 => {
+                r.u8();
+                Mounting::Matrix([r.f32s(), r.f32s(), r.f32s()])
+            }
+            _ => return Err(BlobError::InvalidField),
+        };
+
+        Ok(Self {
+            accel,
+            accel_temp: models[0],
+            gyro_bias,
+            gyro_temp: models[1],
+            mounting,
+        })
+    }
+}
+
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn u8(&mut self, v: u8) {
+        self.buf[self.pos] = v;
+        self.pos += 1;
+    }
+
+    fn f32s(&mut self, values: &[f32]) {
+        for v in values {
+            self.buf[self.pos..self.pos + 4].copy_from_slice(&v.to_le_bytes());
+            self.pos += 4;
+        }
+    }
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> u8 {
+        self.pos += 1;
+        self.buf[self.pos - 1]
+    }
+
+    fn f32s<const N: usize>(&mut self) -> [f32; N] {
+        [(); N].map(|_| {
+            let b = &self.buf[self.pos..self.pos + 4];
+            self.pos += 4;
+            f32::from_le_bytes([b[0], b[1], b[2], b[3]])
+        })
+    }
+}
+
+/// CRC-32 (IEEE 802.3, reflected, as used by zlib)
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+#[cfg(feature = "embedded-storage")]
+pub use flash::{FlashError, FlashStore};
+
+#[cfg(feature = "embedded-storage")]
+mod flash {
+    use embedded_storage_async::nor_flash::NorFlash;
+
+    use super::{BlobError, CalibrationData, BLOB_LEN};
+
+    /// Largest write granularity the store supports
+    const BUFFER_LEN: usize = BLOB_LEN.next_multiple_of(256);
+
+    /// Error of a [`FlashStore`] operation
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    #[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+    pub enum FlashError<E> {
+        Flash(E),
+        Blob(BlobError),
+        /// The offset is not erase-aligned, the region does not fit or the
+        /// flash writes in blocks larger than 256 bytes
+        Unsupported,
+    }
+
+    /// Calibration blob stored in a `NorFlash` region
+    ///
+    /// `offset` must be aligned to the flash's erase size. Saving erases the
+    /// sectors covering the blob.
+    pub struct FlashStore<F> {
+        flash: F,
+        offset: u32,
+    }
+
+    impl<F: NorFlash> FlashStore<F> {
+        pub fn new(flash: F, offset: u32) -> Self {
+            Self { flash, offset }
+        }
+
+        pub fn release(self) -> F {
+            self.flash
+        }
+
+        pub async fn save(&mut self, data: &CalibrationData) -> Result<(), FlashError<F::Error>> {
+            let len = BLOB_LEN.next_multiple_of(F::WRITE_SIZE);
+            let erase_len = len.next_multiple_of(F::ERASE_SIZE);
+            let offset = self.offset as usize;
+            if len > BUFFER_LEN
+                || !offset.is_multiple_of(F::ERASE_SIZE)
+                || offset + erase_len > self.flash.capacity()
+            {
+                return Err(FlashError::Unsupported);
+            }
+            let mut buf = [0xFF; BUFFER_LEN];
+            buf[..BLOB_LEN].copy_from_slice(&data.to_bytes());
+            self.flash
+                .erase(self.offset, (offset + erase_len) as u32)
+                .await
+                .map_err(FlashError::Flash)?;
+            self.flash
+                .write(self.offset, &buf[..len])
+                .await
+                .map_err(FlashError::Flash)
+        }
+
+        pub async fn load(&mut self) -> Result<CalibrationData, FlashError<F::Error>> {
+            let len = BLOB_LEN.next_multiple_of(F::READ_SIZE);
+            if len > BUFFER_LEN || self.offset as usize + len > self.flash.capacity() {
+                return Err(FlashError::Unsupported);
+            }
+            let mut buf = [0; BUFFER_LEN];
+            self.flash
+                .read(self.offset, &mut buf[..len])
+                .await
+                .map_err(FlashError::Flash)?;
+            CalibrationData::from_bytes(&buf[..BLOB_LEN]).map_err(FlashError::Blob)
+        }
+    }
+}
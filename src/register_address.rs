@@ -35,7 +35,9 @@ pub enum GyroRegisters {
     RATE_Z_LSB = 0x06,
     RATE_Z_MSB = 0x07,
 
+    RANGE = 0x0F,
     BANDWIDTH  = 0x10,
+    LPM1 = 0x11,
 
     SOFTRESET = 0x14,
     GYRO_SELF_TEST = 0x3C,
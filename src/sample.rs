@@ -0,0 +1,35 @@
+//! Timestamped, fully corrected samples
+
+/// Duration of one accelerometer sensor time tick in µs
+pub const SENSOR_TIME_TICK_US: f32 = 39.0625;
+
+/// The sensor time counter is 24 bits wide and wraps after about 655 s
+const SENSOR_TIME_MASK: u32 = 0x00FF_FFFF;
+
+/// Acceleration in g in the board frame with the sensor time it was latched at
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct AccelSample {
+    pub xyz: [f32; 3],
+    /// Sensor time in ticks of [`SENSOR_TIME_TICK_US`]
+    pub sensor_time: u32,
+}
+
+impl AccelSample {
+    /// Ticks elapsed since `earlier`, across one wrap of the counter
+    pub fn ticks_since(&self, earlier: &AccelSample) -> u32 {
+        self.sensor_time.wrapping_sub(earlier.sensor_time) & SENSOR_TIME_MASK
+    }
+
+    /// Seconds elapsed since `earlier`, across one wrap of the counter
+    pub fn seconds_since(&self, earlier: &AccelSample) -> f32 {
+        self.ticks_since(earlier) as f32 * SENSOR_TIME_TICK_US * 1e-6
+    }
+}
+
+/// Angular rate in °/s in the board frame
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct GyroSample {
+    pub xyz: [f32; 3],
+}
@@ -0,0 +1,222 @@
+//! One SPI bus shared by the accelerometer and the gyroscope
+//!
+//! On every BMI088 board both dies sit on the same SPI bus and are selected
+//! with separate chip-select lines (CSB1 for the accelerometer, CSB2 for the
+//! gyroscope). [`SharedSpiBus`] owns the bus and hands out one
+//! [`SharedSpiDevice`] per chip select, without requiring an executor
+//! specific mutex.
+
+use core::{
+    cell::{Cell, RefCell, RefMut},
+    future::poll_fn,
+    task::{Poll, Waker},
+};
+
+use embedded_hal::{
+    digital::OutputPin,
+    spi::{ErrorKind, ErrorType, Operation},
+};
+use embedded_hal_async::{
+    delay::DelayNs,
+    spi::{SpiBus, SpiDevice},
+};
+
+use crate::{
+    acc_impl::Accelerometer, gyro_impl::Gyroscope, interface::SpiInterface, Bmi088, Sensor,
+};
+
+/// SPI bus and delay shared by both chip selects
+///
+/// The bus is guarded by a `RefCell`: a device that finds it busy sleeps
+/// until the other device's transaction has finished, so both halves may be
+/// driven from concurrent futures on one executor.
+pub struct SharedSpiBus<BUS, D> {
+    inner: RefCell<(BUS, D)>,
+    /// Device waiting for the bus
+    waiter: Cell<Option<Waker>>,
+}
+
+impl<BUS, D> SharedSpiBus<BUS, D> {
+    pub const fn new(bus: BUS, delay: D) -> Self {
+        Self {
+            inner: RefCell::new((bus, delay)),
+            waiter: Cell::new(None),
+        }
+    }
+
+    /// Give back the bus and the delay
+    pub fn release(self) -> (BUS, D) {
+        self.inner.into_inner()
+    }
+
+    async fn lock(&self) -> RefMut<'_, (BUS, D)> {
+        poll_fn(|cx| match self.inner.try_borrow_mut() {
+            Ok(guard) => Poll::Ready(guard),
+            Err(_) => {
+                // One slot is enough for the two dies. A third device
+                // displaces the waiter, which then polls once more.
+                if let Some(waiter) = self.waiter.take() {
+                    if !waiter.will_wake(cx.waker()) {
+                        waiter.wake();
+                    }
+                }
+                self.waiter.set(Some(cx.waker().clone()));
+                Poll::Pending
+            }
+        })
+        .await
+    }
+
+    fn unlock(&self) {
+        if let Some(waiter) = self.waiter.take() {
+            waiter.wake();
+        }
+    }
+}
+
+/// Error of a [`SharedSpiDevice`] transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum DeviceError<BUS, CS> {
+    /// SPI bus error
+    Spi(BUS),
+    /// Chip-select pin error
+    Cs(CS),
+}
+
+impl<BUS, CS> embedded_hal::spi::Error for DeviceError<BUS, CS>
+where
+    BUS: embedded_hal::spi::Error,
+    CS: core::fmt::Debug,
+{
+    fn kind(&self) -> ErrorKind {
+        match self {
+            DeviceError::Spi(e) => e.kind(),
+            DeviceError::Cs(_) => ErrorKind::ChipSelectFault,
+        }
+    }
+}
+
+/// Locked bus with the chip selected
+///
+/// Dropping it deselects the chip and unlocks the bus, also when a
+/// transaction is abandoned halfway, e.g. by a retry timeout. Otherwise the
+/// next transaction would select the other die as well and both would drive
+/// MISO.
+struct Selected<'a, BUS, CS: OutputPin, D> {
+    shared: &'a SharedSpiBus<BUS, D>,
+    bus: RefMut<'a, (BUS, D)>,
+    cs: &'a mut CS,
+    active: bool,
+}
+
+impl<BUS, CS: OutputPin, D> Selected<'_, BUS, CS, D> {
+    fn deselect(&mut self) -> Result<(), CS::Error> {
+        if !core::mem::take(&mut self.active) {
+            return Ok(());
+        }
+        let result = self.cs.set_high();
+        // The waiter runs after this task yields, by then the borrow is gone
+        self.shared.unlock();
+        result
+    }
+}
+
+impl<BUS, CS: OutputPin, D> Drop for Selected<'_, BUS, CS, D> {
+    fn drop(&mut self) {
+        let _ = self.deselect();
+    }
+}
+
+/// One chip select on a [`SharedSpiBus`]
+pub struct SharedSpiDevice<'a, BUS, CS, D> {
+    bus: &'a SharedSpiBus<BUS, D>,
+    cs: CS,
+}
+
+impl<'a, BUS, CS, D> SharedSpiDevice<'a, BUS, CS, D> {
+    pub fn new(bus: &'a SharedSpiBus<BUS, D>, cs: CS) -> Self {
+        Self { bus, cs }
+    }
+}
+
+impl<BUS, CS, D> ErrorType for SharedSpiDevice<'_, BUS, CS, D>
+where
+    BUS: ErrorType,
+    CS: OutputPin,
+{
+    type Error = DeviceError<BUS::Error, CS::Error>;
+}
+
+impl<BUS, CS, D> SpiDevice for SharedSpiDevice<'_, BUS, CS, D>
+where
+    BUS: SpiBus,
+    CS: OutputPin,
+    D: DelayNs,
+{
+    // Holding the borrow across awaits is the locking scheme: the other
+    // device only ever uses `try_borrow_mut` and waits its turn.
+    #[allow(clippy::await_holding_refcell_ref)]
+    async fn transaction(
+        &mut self,
+        operations: &mut [Operation<'_, u8>],
+    ) -> Result<(), Self::Error> {
+        let mut selected = Selected {
+            shared: self.bus,
+            bus: self.bus.lock().await,
+            cs: &mut self.cs,
+            active: true,
+        };
+        selected.cs.set_low().map_err(DeviceError::Cs)?;
+        let (bus, delay) = &mut *selected.bus;
+        let result = async {
+            for operation in operations {
+                match operation {
+                    Operation::Read(buf) => bus.read(buf).await?,
+                    Operation::Write(buf) => bus.write(buf).await?,
+                    Operation::Transfer(read, write) => bus.transfer(read, write).await?,
+                    Operation::TransferInPlace(buf) => bus.transfer_in_place(buf).await?,
+                    Operation::DelayNs(ns) => {
+                        bus.flush().await?;
+                        delay.delay_ns(*ns).await;
+                    }
+                }
+            }
+            bus.flush().await
+        }
+        .await;
+        let cs_result = selected.deselect();
+
+        result.map_err(DeviceError::Spi)?;
+        cs_result.map_err(DeviceError::Cs)
+    }
+}
+
+impl<BUS, D> Bmi088<SharedSpiBus<BUS, D>> {
+    /// Create both halves of the BMI088 on one SPI bus
+    ///
+    /// `acc_cs` drives CSB1 and `gyro_cs` drives CSB2. Both pins must be
+    /// high (deselected) before the first transfer. The accelerometer still
+    /// has to be switched to SPI mode with [`Accelerometer::dummy_read`].
+    #[allow(clippy::type_complexity)]
+    pub fn new_with_shared_spi<'a, ACS, GCS>(
+        bus: &'a SharedSpiBus<BUS, D>,
+        acc_cs: ACS,
+        gyro_cs: GCS,
+    ) -> (
+        Accelerometer<SpiInterface<SharedSpiDevice<'a, BUS, ACS, D>>>,
+        Gyroscope<SpiInterface<SharedSpiDevice<'a, BUS, GCS, D>>>,
+    ) {
+        let acc = Accelerometer::from_iface(SpiInterface {
+            spi: SharedSpiDevice::new(bus, acc_cs),
+            has_dummy_byte: true,
+            sensor: Sensor::Accelerometer,
+        });
+        let gyro = Gyroscope::from_iface(SpiInterface {
+            spi: SharedSpiDevice::new(bus, gyro_cs),
+            has_dummy_byte: false,
+            sensor: Sensor::Gyroscope,
+        });
+        (acc, gyro)
+    }
+}
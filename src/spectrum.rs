@@ -0,0 +1,262 @@
+//! Vibration spectra of accelerometer or gyroscope samples
+//!
+//! A [`SpectrumAnalyzer`] holds a window of `N` samples, filled by
+//! [`Accelerometer::capture`](crate::acc_impl::Accelerometer::capture) or by
+//! pushing samples from a data-ready interrupt. Its [`Spectrum`]s are in the
+//! units of the samples, g for the accelerometer and °/s for the gyroscope.
+
+use core::f32::consts::PI;
+
+use crate::math::fft;
+
+/// Window function applied before the transform
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub enum Window {
+    /// No weighting, only for transients shorter than the window
+    Rectangular,
+    /// General purpose
+    #[default]
+    Hann,
+    Hamming,
+    /// Low leakage for weak peaks next to strong ones
+    Blackman,
+    /// Accurate amplitudes between bins at the cost of resolution
+    FlatTop,
+}
+
+impl Window {
+    /// Weight of sample `n` of `len`
+    pub fn weight(&self, n: usize, len: usize) -> f32 {
+        let phase = 2.0 * PI * n as f32 / len as f32;
+        let cos = |k: f32| libm::cosf(k * phase);
+        match self {
+            Window::Rectangular => 1.0,
+            Window::Hann => 0.5 - 0.5 * cos(1.0),
+            Window::Hamming => 0.54 - 0.46 * cos(1.0),
+            Window::Blackman => 0.42 - 0.5 * cos(1.0) + 0.08 * cos(2.0),
+            Window::FlatTop => {
+                0.215_578_95 - 0.416_631_58 * cos(1.0) + 0.277_263_16 * cos(2.0)
+                    - 0.083_578_95 * cos(3.0)
+                    + 0.006_947_368 * cos(4.0)
+            }
+        }
+    }
+
+    /// Bins on either side of a peak that hold most of its power
+    fn lobe_bins(&self) -> usize {
+        match self {
+            Window::Rectangular | Window::Hann | Window::Hamming => 1,
+            Window::Blackman => 2,
+            Window::FlatTop => 4,
+        }
+    }
+}
+
+/// Spectral peak of a sinusoidal vibration
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Peak {
+    /// Interpolated between bins
+    pub frequency_hz: f32,
+    /// Peak amplitude of the sinusoid in sample units
+    pub amplitude: f32,
+}
+
+/// One-sided power spectrum of one axis
+///
+/// Bin `k` covers `k * resolution_hz`, from DC up to the Nyquist frequency.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Spectrum<const N: usize> {
+    /// Only the first `N / 2 + 1` entries are used
+    power: [f32; N],
+    resolution_hz: f32,
+    /// Equivalent noise bandwidth of the window in bins
+    enbw: f32,
+    window: Window,
+}
+
+impl<const N: usize> Spectrum<N> {
+    /// Number of bins, `N / 2 + 1`
+    pub fn bin_count(&self) -> usize {
+        N / 2 + 1
+    }
+
+    /// Bin spacing in Hz
+    pub fn resolution_hz(&self) -> f32 {
+        self.resolution_hz
+    }
+
+    pub fn frequency_hz(&self, bin: usize) -> f32 {
+        bin as f32 * self.resolution_hz
+    }
+
+    /// Power per bin in squared sample units
+    ///
+    /// A sinusoid on a bin shows its mean square, half its squared
+    /// amplitude.
+    pub fn power(&self) -> &[f32] {
+        &self.power[..self.bin_count()]
+    }
+
+    /// Power spectral density of `bin` in squared sample units per Hz
+    pub fn density(&self, bin: usize) -> f32 {
+        self.power[bin] / (self.enbw * self.resolution_hz)
+    }
+
+    /// RMS over the bins from `low_hz` to `high_hz` inclusive
+    ///
+    /// The mean was removed before the transform, so a band starting at DC
+    /// only contains what is left of it after windowing.
+    pub fn band_rms(&self, low_hz: f32, high_hz: f32) -> f32 {
+        let sum: f32 = self.bin_range(low_hz, high_hz).map(|k| self.power[k]).sum();
+        libm::sqrtf(sum / self.enbw)
+    }
+
+    /// Up to `P` strongest local maxima between `low_hz` and `high_hz`,
+    /// strongest first
+    pub fn peaks<const P: usize>(&self, low_hz: f32, high_hz: f32) -> [Option<Peak>; P] {
+        let mut found: [Option<(f32, usize)>; P] = [None; P];
+        let last = self.bin_count() - 1;
+        for k in self.bin_range(low_hz, high_hz) {
+            let p = self.power[k];
+            let below = k.checked_sub(1).map_or(0.0, |k| self.power[k]);
+            let above = if k < last { self.power[k + 1] } else { 0.0 };
+            if p <= below || p < above || p <= 0.0 {
+                continue;
+            }
+            let Some(slot) = found.iter().position(|f| f.is_none_or(|(q, _)| p > q)) else {
+                continue;
+            };
+            found.copy_within(slot..P - 1, slot + 1);
+            found[slot] = Some((p, k));
+        }
+        found.map(|f| f.map(|(_, k)| self.peak(k)))
+    }
+
+    fn peak(&self, k: usize) -> Peak {
+        let last = self.bin_count() - 1;
+        let [a, b, c] = [k.saturating_sub(1), k, (k + 1).min(last)].map(|k| self.power[k]);
+        // Gaussian interpolation, exact for the main lobe of a Gaussian
+        // window and close for the others
+        let offset = if k > 0 && k < last && a > 0.0 && c > 0.0 {
+            let [a, b, c] = [a, b, c].map(libm::logf);
+            let denominator = a - 2.0 * b + c;
+            if denominator < 0.0 {
+                (0.5 * (a - c) / denominator).clamp(-0.5, 0.5)
+            } else {
+                0.0
+            }
+        } else {
+            0.0
+        };
+        // The main lobe spreads the power over neighbouring bins
+        let half = self.window.lobe_bins();
+        let lobe: f32 = self.power[k.saturating_sub(half)..=(k + half).min(last)]
+            .iter()
+            .sum();
+        Peak {
+            frequency_hz: (k as f32 + offset) * self.resolution_hz,
+            amplitude: libm::sqrtf(2.0 * lobe / self.enbw),
+        }
+    }
+
+    fn bin_range(&self, low_hz: f32, high_hz: f32) -> impl Iterator<Item = usize> {
+        let low = libm::ceilf(low_hz / self.resolution_hz).max(0.0) as usize;
+        let high = libm::floorf(high_hz / self.resolution_hz).max(0.0) as usize;
+        low..=high.min(self.bin_count() - 1)
+    }
+}
+
+/// Collects `N` three-axis samples for spectral analysis
+///
+/// `N` must be a power of two. The resolution is the sample rate divided by
+/// `N`, so 1024 samples at 1600 Hz resolve 1.6 Hz.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct SpectrumAnalyzer<const N: usize> {
+    samples: [[f32; 3]; N],
+    len: usize,
+    sample_rate_hz: f32,
+}
+
+impl<const N: usize> SpectrumAnalyzer<N> {
+    const POWER_OF_TWO: () = assert!(N.is_power_of_two() && N >= 4);
+
+    pub const fn new(sample_rate_hz: f32) -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::POWER_OF_TWO;
+        SpectrumAnalyzer {
+            samples: [[0.0; 3]; N],
+            len: 0,
+            sample_rate_hz,
+        }
+    }
+
+    pub fn sample_rate_hz(&self) -> f32 {
+        self.sample_rate_hz
+    }
+
+    /// Change the sample rate, dropping collected samples if it differs
+    pub fn set_sample_rate(&mut self, sample_rate_hz: f32) {
+        if self.sample_rate_hz != sample_rate_hz {
+            self.sample_rate_hz = sample_rate_hz;
+            self.clear();
+        }
+    }
+
+    /// Add a sample, returns whether the window is full
+    ///
+    /// Once full, further samples are ignored until [`Self::clear`].
+    pub fn push(&mut self, x: [f32; 3]) -> bool {
+        if let Some(slot) = self.samples.get_mut(self.len) {
+            *slot = x;
+            self.len += 1;
+        }
+        self.is_full()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len == N
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    pub fn samples(&self) -> &[[f32; 3]] {
+        &self.samples[..self.len]
+    }
+
+    /// Spectrum of `axis` after removing the mean and applying `window`,
+    /// `None` until the window is full
+    pub fn spectrum(&self, axis: usize, window: Window) -> Option<Spectrum<N>> {
+        if !self.is_full() || axis > 2 {
+            return None;
+        }
+        let mean = self.samples.iter().map(|s| s[axis]).sum::<f32>() / N as f32;
+        let mut x = [[0.0f32; 2]; N];
+        let (mut sum, mut sum_sq) = (0.0f32, 0.0f32);
+        for (n, (x, s)) in x.iter_mut().zip(&self.samples).enumerate() {
+            let w = window.weight(n, N);
+            sum += w;
+            sum_sq += w * w;
+            *x = [(s[axis] - mean) * w, 0.0];
+        }
+        fft(&mut x);
+
+        let mut power = [0.0; N];
+        let scale = 1.0 / (sum * sum);
+        for (k, (p, [re, im])) in power.iter_mut().zip(x).take(N / 2 + 1).enumerate() {
+            let one_sided = if k == 0 || k == N / 2 { 1.0 } else { 2.0 };
+            *p = one_sided * scale * (re * re + im * im);
+        }
+        Some(Spectrum {
+            power,
+            resolution_hz: self.sample_rate_hz / N as f32,
+            enbw: N as f32 * sum_sq / (sum * sum),
+            window,
+        })
+    }
+}
@@ -0,0 +1,170 @@
+//! Statistics of accelerometer or gyroscope samples over fixed windows
+//!
+//! A [`WindowStatistics`] accumulates samples one at a time and hands out a
+//! [`Summary`] each time its window is complete, e.g. once per second for
+//! condition monitoring. Results are in the units of the samples.
+
+/// Statistics of one signal over a window
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct SignalStatistics {
+    pub mean: f32,
+    /// Population variance around [`Self::mean`]
+    pub variance: f32,
+    /// Root mean square, including the mean
+    pub rms: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+impl SignalStatistics {
+    pub fn std_dev(&self) -> f32 {
+        libm::sqrtf(self.variance)
+    }
+
+    pub fn peak_to_peak(&self) -> f32 {
+        self.max - self.min
+    }
+
+    /// Largest absolute value
+    pub fn peak(&self) -> f32 {
+        self.max.abs().max(self.min.abs())
+    }
+
+    /// Peak over RMS, 0 for a silent signal
+    ///
+    /// √2 for a sinusoid, larger for impacts. Remove gravity or a bias
+    /// first, otherwise the mean dominates both.
+    pub fn crest_factor(&self) -> f32 {
+        if self.rms > 0.0 {
+            self.peak() / self.rms
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Statistics of a completed window
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct Summary {
+    /// Samples in the window
+    pub samples: u32,
+    pub axes: [SignalStatistics; 3],
+    /// Statistics of the vector magnitude of each sample
+    pub magnitude: SignalStatistics,
+}
+
+/// Running sums of one signal, Welford's update keeps the variance exact
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+struct Accumulator {
+    mean: f64,
+    m2: f64,
+    min: f32,
+    max: f32,
+}
+
+impl Accumulator {
+    const EMPTY: Self = Accumulator {
+        mean: 0.0,
+        m2: 0.0,
+        min: f32::INFINITY,
+        max: f32::NEG_INFINITY,
+    };
+
+    fn push(&mut self, x: f32, n: u32) {
+        let delta = f64::from(x) - self.mean;
+        self.mean += delta / f64::from(n);
+        self.m2 += delta * (f64::from(x) - self.mean);
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+    }
+
+    fn statistics(&self, n: u32) -> SignalStatistics {
+        let variance = self.m2 / f64::from(n);
+        SignalStatistics {
+            mean: self.mean as f32,
+            variance: variance as f32,
+            rms: libm::sqrt(variance + self.mean * self.mean) as f32,
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+/// Per-axis and magnitude statistics over windows of a fixed sample count
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "defmt-03", derive(defmt::Format))]
+pub struct WindowStatistics {
+    window: u32,
+    count: u32,
+    axes: [Accumulator; 3],
+    magnitude: Accumulator,
+}
+
+impl WindowStatistics {
+    /// Windows of `window` samples, at least one
+    pub const fn new(window: u32) -> Self {
+        WindowStatistics {
+            window: if window == 0 { 1 } else { window },
+            count: 0,
+            axes: [Accumulator::EMPTY; 3],
+            magnitude: Accumulator::EMPTY,
+        }
+    }
+
+    /// Windows of `seconds` at `sample_rate_hz`, e.g. the ODR
+    pub fn with_duration(seconds: f32, sample_rate_hz: f32) -> Self {
+        Self::new(libm::roundf(seconds * sample_rate_hz) as u32)
+    }
+
+    pub fn window(&self) -> u32 {
+        self.window
+    }
+
+    /// Change the window length, restarting the current window
+    pub fn set_window(&mut self, window: u32) {
+        *self = Self::new(window);
+    }
+
+    /// Samples in the current window
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Add a sample, returns the summary when it completes the window
+    pub fn push(&mut self, x: [f32; 3]) -> Option<Summary> {
+        self.count += 1;
+        for (axis, x) in self.axes.iter_mut().zip(x) {
+            axis.push(x, self.count);
+        }
+        let magnitude = libm::sqrtf(x[0] * x[0] + x[1] * x[1] + x[2] * x[2]);
+        self.magnitude.push(magnitude, self.count);
+        if self.count < self.window {
+            return None;
+        }
+        let summary = self.summary();
+        self.reset();
+        summary
+    }
+
+    /// Statistics of the samples so far, `None` if the window is empty
+    pub fn summary(&self) -> Option<Summary> {
+        if self.count == 0 {
+            return None;
+        }
+        Some(Summary {
+            samples: self.count,
+            axes: self.axes.map(|a| a.statistics(self.count)),
+            magnitude: self.magnitude.statistics(self.count),
+        })
+    }
+
+    /// Discard the current window
+    pub fn reset(&mut self) {
+        self.count = 0;
+        self.axes = [Accumulator::EMPTY; 3];
+        self.magnitude = Accumulator::EMPTY;
+    }
+}
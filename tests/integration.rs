@@ -6,6 +6,12 @@ use panic_probe as _;
 
 #[defmt_test::tests]
 mod tests {
+    use bmi088::{
+        inclinometer::Tilt,
+        orientation::{AxisRotation, Mounting},
+        persistence::{crc32, BlobError, CalibrationData},
+    };
+
     #[init]
     fn init() {
         let _ = embassy_stm32::init(Default::default());
@@ -18,4 +24,45 @@ mod tests {
         let temperature = (((temp_msb as i8) as i16) << 3) | (((temp_lsb as u16) >> 5) as i16);
         assert_eq!(temperature, -504);
     }
+
+    #[test]
+    fn test_axis_rotations_are_proper() {
+        for (i, rotation) in AxisRotation::ALL.iter().enumerate() {
+            let m = rotation.matrix();
+            let det = m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+                - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+                + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0]);
+            assert_eq!(det, 1.0);
+            for other in &AxisRotation::ALL[i + 1..] {
+                assert_ne!(m, other.matrix());
+            }
+        }
+    }
+
+    #[test]
+    fn test_calibration_blob_round_trip() {
+        let data = CalibrationData {
+            gyro_bias: [0.25, -0.1, 0.05],
+            mounting: Mounting::Aligned(AxisRotation::PyNx),
+            ..Default::default()
+        };
+        let mut blob = data.to_bytes();
+        assert!(CalibrationData::from_bytes(&blob) == Ok(data));
+
+        blob[12] ^= 0x01;
+        assert!(CalibrationData::from_bytes(&blob) == Err(BlobError::BadCrc));
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+    }
+
+    #[test]
+    fn test_tilt_is_defined_at_vertical_x() {
+        let tilt = Tilt::from_gravity([-1.0, 0.0, 0.0], 1e-3, 1);
+        assert!((tilt.pitch_deg() - 90.0).abs() < 1e-4);
+        assert_eq!(tilt.roll, 0.0);
+        assert_eq!(tilt.roll_std, core::f32::consts::PI);
+
+        let tilt = Tilt::from_gravity([0.0, 0.5, 0.866_025_4], 1e-3, 1);
+        assert!((tilt.roll_deg() - 30.0).abs() < 1e-3);
+        assert!((tilt.roll_std - 1e-3).abs() < 1e-6);
+    }
 }